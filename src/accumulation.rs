@@ -0,0 +1,208 @@
+use std::{collections::hash_map::DefaultHasher, hash::Hasher, io};
+
+use eframe::glow::{self, HasContext as _};
+use nalgebra::{Matrix4, Vector2, Vector3};
+use serde::Serialize;
+
+use crate::{error::GlError, framebuffer::{ColorFormat, Framebuffer}, mesh::Mesh, shader::ShaderProgram, uniforms::TextureUnit};
+
+// Offscreen rendering of the viewport. While nothing changes, every frame renders one
+// more sample with the pixel centers shifted by a sub-pixel jitter, and the float
+// framebuffer keeps the running average of all of them for progressive anti-aliasing.
+// The first sample is unjittered, so the image looks the same as without accumulation
+// while navigating. The buffer can be smaller than the viewport, see resolution.rs.
+
+
+const BLIT_VERT: &str = include_str!("main.vert.glsl");
+const BLIT_FRAG: &str = include_str!("blit.frag.glsl");
+
+
+/// Radical inverse of `index` in `base`, a low-discrepancy sequence in 0..1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut f = 1.0;
+    while index > 0 {
+        f /= base as f32;
+        result += f * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+
+/// Everything the rendered image depends on. Accumulation starts over when any of it
+/// changes.
+#[derive(PartialEq)]
+pub struct SceneKey {
+    pub program: glow::Program,
+    pub view_proj: Matrix4<f32>,
+    pub camera_pos: Vector3<f32>,
+    /// `settings_hash` of the scene parameters and shader uniforms
+    pub settings: u64,
+}
+
+
+/// Hashes `settings` through their serialized form, so the key notices any change without
+/// holding its own copy of the scene graph.
+pub fn settings_hash(settings: &impl Serialize) -> u64 {
+    struct HashWriter<'a>(&'a mut DefaultHasher);
+
+    impl io::Write for HashWriter<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut hasher = DefaultHasher::new();
+    // neither the writer nor the scene types have a way to fail
+    let _ = serde_json::to_writer(HashWriter(&mut hasher), settings);
+    hasher.finish()
+}
+
+
+pub struct Accumulator {
+    framebuffer: Framebuffer,
+    blit: ShaderProgram,
+    samples: u32,
+    key: Option<SceneKey>,
+    /// Whether the last `track` saw a change
+    moving: bool,
+}
+
+impl Accumulator {
+    pub fn new(gl: &glow::Context, width: i32, height: i32) -> Result<Self, GlError> {
+        let framebuffer = Framebuffer::new(gl, width, height, ColorFormat::Rgba32F)?;
+        let blit = match ShaderProgram::new(gl, BLIT_VERT, BLIT_FRAG) {
+            Ok(blit) => blit,
+            Err(err) => {
+                framebuffer.destroy(gl);
+                return Err(err);
+            }
+        };
+        Ok(Self { framebuffer, blit, samples: 0, key: None, moving: false })
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Throws away the samples so far.
+    pub fn reset(&mut self) {
+        self.samples = 0;
+    }
+
+    /// Size of the buffer, which is what the scene renders at.
+    pub fn size(&self) -> (i32, i32) {
+        (self.framebuffer.width, self.framebuffer.height)
+    }
+
+    pub fn moving(&self) -> bool {
+        self.moving
+    }
+
+    /// Starts over unless `key` is what the samples so far were rendered with. Returns
+    /// whether it did.
+    pub fn track(&mut self, key: SceneKey) -> bool {
+        self.moving = self.key.as_ref() != Some(&key);
+        if self.moving {
+            self.key = Some(key);
+            self.reset();
+        }
+        self.moving
+    }
+
+    /// Sets the size of the buffer, starting over if it changed. `present` stretches it
+    /// over the viewport.
+    pub fn resize(&mut self, gl: &glow::Context, width: i32, height: i32) -> Result<(), GlError> {
+        if self.framebuffer.resize(gl, width, height)? {
+            self.reset();
+        }
+        Ok(())
+    }
+
+    /// Offset of the next sample's pixel centers in NDC, within half a pixel.
+    fn jitter(&self) -> Vector2<f32> {
+        if self.samples == 0 {
+            return Vector2::zeros();
+        }
+        let offset = Vector2::new(halton(self.samples, 2), halton(self.samples, 3)).add_scalar(-0.5);
+        Vector2::new(2.0 * offset.x / self.framebuffer.width as f32, 2.0 * offset.y / self.framebuffer.height as f32)
+    }
+
+    /// Blends one more sample into the average. `draw` renders the scene with the jitter
+    /// it is given. Leaves the accumulation framebuffer bound.
+    pub fn accumulate(&mut self, gl: &glow::Context, draw: impl FnOnce(Vector2<f32>)) {
+        self.framebuffer.bind(gl);
+        unsafe {
+            gl.disable(glow::SCISSOR_TEST);
+            // the new sample gets weight 1/n, which keeps the buffer at the mean
+            gl.enable(glow::BLEND);
+            gl.blend_equation(glow::FUNC_ADD);
+            gl.blend_func(glow::CONSTANT_ALPHA, glow::ONE_MINUS_CONSTANT_ALPHA);
+            gl.blend_color(0.0, 0.0, 0.0, 1.0 / (self.samples + 1) as f32);
+        }
+
+        draw(self.jitter());
+
+        unsafe {
+            gl.disable(glow::BLEND);
+        }
+        self.samples += 1;
+    }
+
+    /// Draws the average into whatever framebuffer and viewport are bound, scaled to fit,
+    /// with the depth of the last sample.
+    pub fn present(&self, gl: &glow::Context, mesh: &Mesh) {
+        unsafe {
+            gl.disable(glow::BLEND);
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.framebuffer.depth_texture));
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.framebuffer.color_texture));
+        }
+        self.blit.draw(gl, mesh, |gl, uniforms| {
+            uniforms.set(gl, "u_Accum", TextureUnit(0));
+            uniforms.set(gl, "u_Depth", TextureUnit(1));
+        });
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.active_texture(glow::TEXTURE0);
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        self.framebuffer.destroy(gl);
+        self.blit.destroy(gl);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use meshview::sdf_graph::SdfNode;
+
+    use super::*;
+
+    #[test]
+    fn settings_hash_changes_with_any_parameter() {
+        let graph = SdfNode::Sphere { radius: 1.0 };
+        let hash = settings_hash(&(8.0f32, &graph));
+        assert_eq!(hash, settings_hash(&(8.0f32, &graph.clone())));
+        assert_ne!(hash, settings_hash(&(8.5f32, &graph)));
+        assert_ne!(hash, settings_hash(&(8.0f32, &SdfNode::Sphere { radius: 1.01 })));
+    }
+
+    #[test]
+    fn halton_stays_in_the_unit_interval() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((1..64).all(|i| (0.0..1.0).contains(&halton(i, 3))));
+    }
+}
@@ -0,0 +1,351 @@
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+
+
+/// How a keyframe blends into the next one.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Catmull-Rom through the neighbouring keys
+    Cubic,
+    /// Smoothstep, comes to rest on every key
+    Ease,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 3] = [Interpolation::Linear, Interpolation::Cubic, Interpolation::Ease];
+}
+
+
+pub trait Animatable: Clone {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self;
+
+    /// Catmull-Rom spline between `p1` and `p2`.
+    fn cubic(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+
+    fn cubic(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f32) -> Self {
+        let (t2, t3) = (t * t, t * t * t);
+        0.5 * ((2.0 * p1)
+            + (-p0 + p2) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+            + (-p0 + 3.0 * p1 - 3.0 * p2 + p3) * t3)
+    }
+}
+
+impl Animatable for Vector3<f32> {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a.lerp(b, t)
+    }
+
+    fn cubic(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f32) -> Self {
+        Vector3::from_fn(|i, _| f32::cubic(&p0[i], &p1[i], &p2[i], &p3[i], t))
+    }
+}
+
+impl Animatable for UnitQuaternion<f32> {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a.try_slerp(b, t, 1.0e-6).unwrap_or(*b)
+    }
+
+    // a true squad isn't worth it here, slerp with the spline's timing is smooth enough
+    fn cubic(_p0: &Self, p1: &Self, p2: &Self, _p3: &Self, t: f32) -> Self {
+        <Self as Animatable>::lerp(p1, p2, t)
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    /// Used for the segment from this key to the next one
+    pub interpolation: Interpolation,
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Track<T> {
+    pub keys: Vec<Keyframe<T>>,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    /// Adds a key, replacing one that sits at (almost) the same time.
+    pub fn insert(&mut self, time: f32, value: T, interpolation: Interpolation) {
+        let key = Keyframe { time, value, interpolation };
+
+        match self.keys.iter().position(|k| (k.time - time).abs() < 1.0e-3) {
+            Some(i) => self.keys[i] = key,
+            None => {
+                let i = self.keys.partition_point(|k| k.time < time);
+                self.keys.insert(i, key);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    /// Value at `time`, holding the first/last key outside the keyed range.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let keys = &self.keys;
+        let first = keys.first()?;
+        let last = keys.last()?;
+
+        if time <= first.time {
+            return Some(first.value.clone());
+        }
+        if time >= last.time {
+            return Some(last.value.clone());
+        }
+
+        let i = keys.partition_point(|k| k.time <= time).saturating_sub(1);
+        let (k1, k2) = (&keys[i], &keys[i + 1]);
+        let t = (time - k1.time) / (k2.time - k1.time);
+
+        Some(match k1.interpolation {
+            Interpolation::Linear => T::lerp(&k1.value, &k2.value, t),
+            Interpolation::Ease => T::lerp(&k1.value, &k2.value, t * t * (3.0 - 2.0 * t)),
+            Interpolation::Cubic => {
+                let p0 = &keys[i.saturating_sub(1)].value;
+                let p3 = &keys[(i + 2).min(keys.len() - 1)].value;
+                T::cubic(p0, &k1.value, &k2.value, p3, t)
+            }
+        })
+    }
+}
+
+
+/// Values the timeline drives at one point in time. `None` means the track has no keys and
+/// the parameter is left alone.
+#[derive(Clone, Debug, Default)]
+pub struct TimelineSample {
+    pub position: Option<Vector3<f32>>,
+    pub orientation: Option<UnitQuaternion<f32>>,
+    pub exp: Option<f32>,
+    pub iterations: Option<u32>,
+    pub detail: Option<i32>,
+    pub palette: [Option<Vector3<f32>>; 3],
+}
+
+impl TimelineSample {
+    /// Moves `camera` to the keyed position and orientation.
+    pub fn apply_camera(&self, camera: &mut Camera) {
+        if let Some(position) = self.position {
+            camera.pos = position;
+        }
+        if let Some(orientation) = self.orientation {
+            camera.orientation = orientation;
+        }
+    }
+
+    /// Overwrites the keyed fractal parameters and palette colors.
+    pub fn apply_params(&self, exp: &mut f32, iterations: &mut u32, detail: &mut i32, palette: &mut [Vector3<f32>; 3]) {
+        if let Some(keyed) = self.exp {
+            *exp = keyed;
+        }
+        if let Some(keyed) = self.iterations {
+            *iterations = keyed;
+        }
+        if let Some(keyed) = self.detail {
+            *detail = keyed;
+        }
+        for (color, keyed) in palette.iter_mut().zip(self.palette) {
+            if let Some(keyed) = keyed {
+                *color = keyed;
+            }
+        }
+    }
+}
+
+
+/// One keyframe track per animatable parameter.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Timeline {
+    pub duration: f32,
+    pub looping: bool,
+    pub position: Track<Vector3<f32>>,
+    pub orientation: Track<UnitQuaternion<f32>>,
+    pub exp: Track<f32>,
+    pub iterations: Track<f32>,
+    pub detail: Track<f32>,
+    pub palette: [Track<Vector3<f32>>; 3],
+}
+
+impl Default for Timeline {
+    // sweeps the exponent 10 -> 20 -> 10 -> 0 -> 10, like the old sine animation
+    fn default() -> Self {
+        let mut exp = Track::default();
+        for (time, value) in [(0.0, 10.0), (12.0, 20.0), (24.0, 10.0), (36.0, 0.0), (48.0, 10.0)] {
+            exp.insert(time, value, Interpolation::Cubic);
+        }
+
+        Self {
+            duration: 48.0,
+            looping: true,
+            position: Track::default(),
+            orientation: Track::default(),
+            exp,
+            iterations: Track::default(),
+            detail: Track::default(),
+            palette: Default::default(),
+        }
+    }
+}
+
+impl Timeline {
+    /// Maps any time onto the timeline, wrapping when looping and clamping otherwise. NaN
+    /// and infinite times go to the start.
+    pub fn wrap_time(&self, time: f32) -> f32 {
+        if self.duration <= 0.0 || !time.is_finite() {
+            0.0
+        } else if self.looping {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        }
+    }
+
+    pub fn sample(&self, time: f32) -> TimelineSample {
+        let time = self.wrap_time(time);
+
+        TimelineSample {
+            position: self.position.sample(time),
+            orientation: self.orientation.sample(time),
+            exp: self.exp.sample(time),
+            iterations: self.iterations.sample(time).map(|x| x.round().max(1.0) as u32),
+            detail: self.detail.sample(time).map(|x| x.round().max(1.0) as i32),
+            palette: [0, 1, 2].map(|i| self.palette[i].sample(time)),
+        }
+    }
+
+    /// Times of every key on every track, for drawing markers.
+    pub fn key_times(&self) -> Vec<f32> {
+        let mut times = Vec::new();
+        times.extend(self.position.keys.iter().map(|k| k.time));
+        times.extend(self.orientation.keys.iter().map(|k| k.time));
+        times.extend(self.exp.keys.iter().map(|k| k.time));
+        times.extend(self.iterations.keys.iter().map(|k| k.time));
+        times.extend(self.detail.keys.iter().map(|k| k.time));
+        for track in &self.palette {
+            times.extend(track.keys.iter().map(|k| k.time));
+        }
+        times
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(keys: &[(f32, f32)], interpolation: Interpolation) -> Track<f32> {
+        let mut track = Track::default();
+        for &(time, value) in keys {
+            track.insert(time, value, interpolation);
+        }
+        track
+    }
+
+    #[test]
+    fn insert_keeps_keys_sorted_and_replaces_close_ones() {
+        let mut track = track(&[(2.0, 20.0), (0.0, 0.0), (1.0, 10.0)], Interpolation::Linear);
+        assert_eq!(track.keys.iter().map(|k| k.time).collect::<Vec<_>>(), [0.0, 1.0, 2.0]);
+
+        track.insert(1.0005, 15.0, Interpolation::Ease);
+        assert_eq!(track.keys.len(), 3);
+        assert_eq!(track.keys[1].value, 15.0);
+        assert_eq!(track.keys[1].interpolation, Interpolation::Ease);
+    }
+
+    #[test]
+    fn empty_track_has_no_value() {
+        assert_eq!(Track::<f32>::default().sample(1.0), None);
+    }
+
+    #[test]
+    fn linear_holds_the_ends_and_blends_between_keys() {
+        let track = track(&[(1.0, 10.0), (3.0, 30.0)], Interpolation::Linear);
+        assert_eq!(track.sample(0.0), Some(10.0));
+        assert_eq!(track.sample(2.0), Some(20.0));
+        assert_eq!(track.sample(2.5), Some(25.0));
+        assert_eq!(track.sample(5.0), Some(30.0));
+    }
+
+    #[test]
+    fn ease_is_symmetric_about_the_midpoint() {
+        let track = track(&[(0.0, 0.0), (1.0, 1.0)], Interpolation::Ease);
+        assert_eq!(track.sample(0.5), Some(0.5));
+        let (a, b) = (track.sample(0.25).unwrap(), track.sample(0.75).unwrap());
+        assert!(a < 0.25 && (a + b - 1.0).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn cubic_passes_through_keys_and_follows_a_line() {
+        let line = track(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)], Interpolation::Cubic);
+        // the end segments repeat their outer key, so only the middle one is straight
+        for time in [0.0, 1.0, 1.25, 1.5, 2.0, 3.0] {
+            let value = line.sample(time).unwrap();
+            assert!((value - time).abs() < 1.0e-5, "{time}: {value}");
+        }
+
+        let peak = track(&[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)], Interpolation::Cubic);
+        assert_eq!(peak.sample(1.0), Some(1.0));
+        // the spline overshoots a linear blend towards the peak
+        assert!(peak.sample(0.75).unwrap() > 0.75);
+    }
+
+    #[test]
+    fn sampling_nan_does_not_panic() {
+        let track = track(&[(0.0, 1.0), (1.0, 2.0)], Interpolation::Linear);
+        assert!(track.sample(f32::NAN).is_some());
+    }
+
+    #[test]
+    fn wrap_time_loops_or_clamps() {
+        let mut timeline = Timeline { duration: 10.0, looping: true, ..Timeline::default() };
+        assert_eq!(timeline.wrap_time(12.5), 2.5);
+        assert_eq!(timeline.wrap_time(-2.5), 7.5);
+
+        timeline.looping = false;
+        assert_eq!(timeline.wrap_time(12.5), 10.0);
+        assert_eq!(timeline.wrap_time(-2.5), 0.0);
+
+        for time in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(timeline.wrap_time(time), 0.0);
+        }
+        timeline.duration = 0.0;
+        assert_eq!(timeline.wrap_time(5.0), 0.0);
+    }
+
+    #[test]
+    fn timeline_sample_rounds_counts_to_at_least_one() {
+        let mut timeline = Timeline::default();
+        timeline.iterations.insert(0.0, 0.2, Interpolation::Linear);
+        timeline.detail.insert(0.0, 3.6, Interpolation::Linear);
+        let sample = timeline.sample(f32::NAN);
+        assert_eq!(sample.iterations, Some(1));
+        assert_eq!(sample.detail, Some(4));
+        assert_eq!(sample.exp, Some(10.0));
+    }
+}
@@ -0,0 +1,17 @@
+#version 330 core
+
+// Shows the accumulated average from accumulation.rs on a fullscreen quad, along with
+// the depth of the latest sample.
+
+in vec2 fs_uv;
+
+uniform sampler2D u_Accum;
+uniform sampler2D u_Depth;
+
+out vec4 frag_color;
+
+void main() {
+    vec2 uv = fs_uv * 0.5 + 0.5;
+    frag_color = vec4(texture(u_Accum, uv).rgb, 1.0);
+    gl_FragDepth = texture(u_Depth, uv).r;
+}
@@ -0,0 +1,72 @@
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+
+
+/// A named camera state the user can jump back to.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bookmark {
+    pub name: String,
+    pub pos: Vector3<f32>,
+    pub look: Vector3<f32>,
+    pub right: Vector3<f32>,
+    pub fov: f32,
+}
+
+impl Bookmark {
+    pub fn from_camera(name: impl Into<String>, camera: &Camera) -> Self {
+        Self {
+            name: name.into(),
+            pos: camera.pos,
+            look: camera.look(),
+            right: camera.right(),
+            fov: camera.fov,
+        }
+    }
+}
+
+
+/// Smooth camera flight towards a bookmark. Position and fov are lerped and the orientation
+/// is slerped, both on an ease-in-out curve.
+pub struct FlyTo {
+    from_pos: Vector3<f32>,
+    to_pos: Vector3<f32>,
+    from_rot: UnitQuaternion<f32>,
+    to_rot: UnitQuaternion<f32>,
+    from_fov: f32,
+    to_fov: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl FlyTo {
+    pub fn new(camera: &Camera, target: &Bookmark, duration: f32) -> Self {
+        Self {
+            from_pos: camera.pos,
+            to_pos: target.pos,
+            from_rot: camera.orientation,
+            to_rot: Camera::orientation_from_vectors(target.look, target.right),
+            from_fov: camera.fov,
+            to_fov: target.fov,
+            elapsed: 0.0,
+            duration,
+        }
+    }
+
+    /// Advances the flight by `dt` seconds and moves the camera. Returns true once arrived.
+    pub fn step(&mut self, dt: f32, camera: &mut Camera) -> bool {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+
+        let t = if self.duration > 0.0 { self.elapsed / self.duration } else { 1.0 };
+        let t = t * t * (3.0 - 2.0 * t);
+
+        camera.pos = self.from_pos.lerp(&self.to_pos, t);
+        camera.fov = self.from_fov + (self.to_fov - self.from_fov) * t;
+        // try_slerp gives up when both orientations are (almost) the same, nothing to blend then
+        let rot = self.from_rot.try_slerp(&self.to_rot, t, 1.0e-6).unwrap_or(self.to_rot);
+        camera.orientation = rot;
+
+        self.elapsed >= self.duration
+    }
+}
@@ -1,49 +1,116 @@
-use nalgebra::{Matrix4, Orthographic3, Perspective3, Vector3};
+use nalgebra::{Matrix3, Matrix4, Orthographic3, Perspective3, Rotation3, UnitQuaternion, Vector3};
 
+/// Near clip plane. Small, so rasterized overlays survive flying right up to the surface.
+pub const NEAR_PLANE: f32 = 0.01;
+
+/// Far clip plane, the same as the furthest the raymarch goes, so every hit gets a depth
+/// in range and misses sit exactly on it.
+pub const FAR_PLANE: f32 = 500.0;
+
+#[derive(Clone)]
 pub struct Camera {
     pub pos : Vector3<f32>,
-    pub look : Vector3<f32>,
-    pub right: Vector3<f32>,
-    fov : f32,
+    /// Rotation taking camera space (right = +x, up = +y, look = -z) to world space
+    pub orientation : UnitQuaternion<f32>,
+    pub fov : f32,
     pub aspect_ratio : f32
 }   
 
 
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            UnitQuaternion::identity(), //looking down -z
+            45.0,
+            1.0
+        )
+    }
+}
+
+
 impl Camera{
-    pub fn new(pos : Vector3<f32>, look : Vector3<f32>, right: Vector3<f32>, fov : f32, aspect_ratio : f32) -> Self {
+    pub fn new(pos : Vector3<f32>, orientation : UnitQuaternion<f32>, fov : f32, aspect_ratio : f32) -> Self {
         Self {
             pos,
-            look,
-            right,
+            orientation,
             fov,
             aspect_ratio
         }
     }
 
-    pub fn default() -> Self {
-        Self::new(
-            Vector3::new(0.0, 0.0, 3.0),
-            Vector3::new(0.0, 0.0, -1.0), //forward look vector
-            Vector3::new(1.0, 0.0, 0.0), //right look vector
-            45.0,
-            1.0
-        )
+    /// Orientation with the given look and right vectors, which need not be exactly
+    /// orthonormal.
+    pub fn orientation_from_vectors(look: Vector3<f32>, right: Vector3<f32>) -> UnitQuaternion<f32> {
+        let up = right.cross(&look).normalize();
+        let basis = Matrix3::from_columns(&[up.cross(&-look).normalize(), up, -look.normalize()]);
+        UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(basis))
+    }
+
+    /// Turns the camera towards `target`, keeping `up` pointing up on screen as far as
+    /// possible.
+    pub fn look_at(&mut self, target: Vector3<f32>, up: Vector3<f32>) {
+        let look = target - self.pos;
+        // no roll is defined looking straight along `up`, or at the camera's own position
+        if look.cross(&up).norm_squared() > 1.0e-12 {
+            self.orientation = UnitQuaternion::face_towards(&-look, &up);
+        }
+    }
+
+    // angles are in degrees, same as the Rotation sliders in the UI
+    pub fn set_euler_angles(&mut self, angle: (f32, f32, f32)) {
+        self.orientation = UnitQuaternion::from_euler_angles(
+            angle.0.to_radians(), 
+            angle.1.to_radians(), 
+            angle.2.to_radians()
+        );
+    }
+
+    // inverse of set_euler_angles, in degrees
+    pub fn euler_angles(&self) -> (f32, f32, f32) {
+        let (x, y, z) = self.orientation.euler_angles();
+        (x.to_degrees(), y.to_degrees(), z.to_degrees())
+    }
+
+    pub fn look(&self) -> Vector3<f32> {
+        self.orientation * Vector3::new(0.0, 0.0, -1.0)
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.orientation * Vector3::new(1.0, 0.0, 0.0)
     }
 
     pub fn get_up_vec(& self) -> Vector3<f32> {
-        self.right.cross(&self.look).normalize()
+        self.orientation * Vector3::new(0.0, 1.0, 0.0)
+    }
+
+    /// Rotates the camera about its own axes by the given angles in radians: `pitch`
+    /// about right, `yaw` about up and `roll` about look. Free of gimbal lock, as each
+    /// step is relative to the current orientation.
+    pub fn rotate_local(&mut self, pitch: f32, yaw: f32, roll: f32) {
+        let step = UnitQuaternion::from_scaled_axis(Vector3::new(pitch, yaw, -roll));
+        self.orientation *= step;
+        self.orientation.renormalize();
+    }
+
+    /// Rotates the camera's position and orientation together about `pivot` by `rotation`,
+    /// given in world space.
+    pub fn rotate_about(&mut self, pivot: Vector3<f32>, rotation: UnitQuaternion<f32>) {
+        self.pos = pivot + rotation * (self.pos - pivot);
+        self.orientation = rotation * self.orientation;
+        self.orientation.renormalize();
     }
 
     pub fn get_proj_view_mat(&self) -> Matrix4<f32> {
-        let persp = Perspective3::new(self.aspect_ratio, self.fov, 1.0, 100.0).to_homogeneous();
+        let persp = Perspective3::new(self.aspect_ratio, self.fov, NEAR_PLANE, FAR_PLANE).to_homogeneous();
         let _ortho = Orthographic3::new(-1.0, 1.0, -1.0, 1.0, 1.0, 100.0).to_homogeneous();
 
-        let up = self.get_up_vec();
+        let (right, up, look) = (self.right(), self.get_up_vec(), self.look());
 
         let view_orient = Matrix4::new(
-            self.right.x, self.right.y, self.right.z, 0.0, 
+            right.x, right.y, right.z, 0.0, 
             up.x, up.y, up.z, 0.0, 
-            -self.look.x, -self.look.y, -self.look.z, 0.0, 
+            -look.x, -look.y, -look.z, 0.0, 
             0.0, 0.0, 0.0, 1.0
         );
 
@@ -56,4 +123,73 @@ impl Camera{
 
         persp * (view_orient * view_translate)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).norm() < 1.0e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn orientation_from_vectors_reproduces_the_basis() {
+        let identity = Camera::orientation_from_vectors(-Vector3::z(), Vector3::x());
+        assert!(identity.angle() < 1.0e-6);
+
+        let camera = Camera { orientation: Camera::orientation_from_vectors(Vector3::x(), Vector3::z()), ..Camera::default() };
+        assert_close(camera.look(), Vector3::x());
+        assert_close(camera.right(), Vector3::z());
+        assert_close(camera.get_up_vec(), Vector3::y());
+
+        // unnormalized and slightly skewed input comes out orthonormal, keeping the look
+        let look = Vector3::new(2.0, 0.2, 0.0);
+        let camera = Camera { orientation: Camera::orientation_from_vectors(look, Vector3::new(0.1, 0.0, 1.0)), ..Camera::default() };
+        assert_close(camera.look(), look.normalize());
+        assert!(camera.right().dot(&camera.look()).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn look_at_faces_the_target_without_roll() {
+        let mut camera = Camera { pos: Vector3::new(3.0, 2.0, 1.0), ..Camera::default() };
+        let target = Vector3::new(0.0, 0.5, 0.0);
+        camera.look_at(target, Vector3::y());
+
+        assert_close(camera.look(), (target - camera.pos).normalize());
+        assert!(camera.right().y.abs() < 1.0e-5);
+        assert!(camera.get_up_vec().y > 0.0);
+    }
+
+    #[test]
+    fn look_at_along_up_keeps_the_orientation() {
+        let mut camera = Camera { pos: Vector3::new(0.0, 3.0, 0.0), ..Camera::default() };
+        let before = camera.orientation;
+        camera.look_at(Vector3::zeros(), Vector3::y());
+        assert_eq!(camera.orientation, before);
+        camera.look_at(camera.pos, Vector3::y());
+        assert_eq!(camera.orientation, before);
+    }
+
+    #[test]
+    fn rotate_about_keeps_the_distance_and_the_view_of_the_pivot() {
+        let pivot = Vector3::new(0.5, 0.0, -0.25);
+        let mut camera = Camera { pos: Vector3::new(1.0, 2.0, 3.0), ..Camera::default() };
+        camera.look_at(pivot, Vector3::y());
+        let distance = (camera.pos - pivot).norm();
+
+        for _ in 0..50 {
+            camera.rotate_about(pivot, UnitQuaternion::from_scaled_axis(Vector3::new(0.3, -0.7, 0.2)));
+        }
+        assert!(((camera.pos - pivot).norm() - distance).abs() < 1.0e-4);
+        assert_close(camera.look(), (pivot - camera.pos).normalize());
+    }
+
+    #[test]
+    fn rotate_local_turns_about_the_camera_axes() {
+        let mut camera = Camera::default();
+        camera.rotate_local(0.0, std::f32::consts::FRAC_PI_2, 0.0);
+        assert_close(camera.look(), -Vector3::x());
+        assert_close(camera.get_up_vec(), Vector3::y());
+    }
+}
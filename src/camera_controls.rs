@@ -0,0 +1,211 @@
+use eframe::egui;
+use meshview::camera::Camera;
+use nalgebra::{UnitQuaternion, Vector3};
+
+// Mouse and keyboard handling for the viewport. All schemes move the camera with
+// WASD/QE; in the orbit schemes that drags the target along, which pans the view.
+//
+// Fly: dragging turns the camera about its own axes and Z/C roll it.
+// Orbit: dragging circles the target about the world y axis and tilts over it, stopping
+// short of the poles so the horizon stays level. Scrolling zooms towards the target.
+// Trackball: dragging spins the view about the target like a ball under the cursor, with
+// no fixed up direction. Scrolling zooms.
+
+
+/// Degrees turned per point dragged when flying.
+const FLY_DEGREES_PER_POINT: f32 = 0.1;
+/// Degrees turned per point dragged around the target.
+const ORBIT_DEGREES_PER_POINT: f32 = 0.3;
+/// Radians rolled per frame while Z or C is held.
+const ROLL_SPEED: f32 = 0.02;
+/// Closest the orbit gets to looking straight up or down, as the y component of look.
+const MAX_PITCH: f32 = 0.995;
+/// Closest the camera zooms to the target.
+const MIN_DISTANCE: f32 = 0.01;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    Fly,
+    Orbit,
+    Trackball,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 3] = [CameraMode::Fly, CameraMode::Orbit, CameraMode::Trackball];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CameraMode::Fly => "Fly",
+            CameraMode::Orbit => "Orbit",
+            CameraMode::Trackball => "Trackball",
+        }
+    }
+}
+
+
+pub struct CameraControls {
+    pub mode: CameraMode,
+    /// Point the orbit schemes turn around
+    pub target: Vector3<f32>,
+}
+
+impl Default for CameraControls {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::Fly,
+            target: Vector3::zeros(),
+        }
+    }
+}
+
+impl CameraControls {
+    /// Switches scheme. The orbit schemes start out facing the target.
+    pub fn set_mode(&mut self, mode: CameraMode, camera: &mut Camera) {
+        if mode != CameraMode::Fly && mode != self.mode {
+            camera.look_at(self.target, Vector3::y());
+        }
+        self.mode = mode;
+    }
+
+    /// WASD/QE movement, and roll while flying. `speed` is the Speed slider; Shift doubles
+    /// it and Ctrl slows it down. Does nothing while a text field has focus, so typing a
+    /// bookmark name doesn't fly the camera around.
+    pub fn keys(&mut self, ctx: &egui::Context, camera: &mut Camera, speed: f32) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let speed = ctx.input(|i| if i.modifiers.shift { speed * 2.0 } else if i.modifiers.ctrl { speed * 0.2 } else { speed });
+
+        let mut step = Vector3::zeros();
+        for (key, direction) in [
+            (egui::Key::W, camera.look()),
+            (egui::Key::S, -camera.look()),
+            (egui::Key::D, camera.right()),
+            (egui::Key::A, -camera.right()),
+            (egui::Key::E, camera.get_up_vec()),
+            (egui::Key::Q, -camera.get_up_vec()),
+        ] {
+            if ctx.input(|i| i.key_down(key)) {
+                step += direction * 0.01 * speed;
+            }
+        }
+        camera.pos += step;
+
+        match self.mode {
+            CameraMode::Fly => {
+                let roll = ctx.input(|i| i.key_down(egui::Key::C) as i32 - i.key_down(egui::Key::Z) as i32);
+                if roll != 0 {
+                    camera.rotate_local(0.0, 0.0, roll as f32 * ROLL_SPEED);
+                }
+            }
+            CameraMode::Orbit | CameraMode::Trackball => self.target += step,
+        }
+    }
+
+    /// Drag to turn and scroll to zoom over the viewport.
+    pub fn pointer(&mut self, ctx: &egui::Context, response: &egui::Response, camera: &mut Camera) {
+        self.drag(response.drag_motion(), camera);
+
+        if self.mode != CameraMode::Fly && response.hovered() {
+            let scroll = ctx.input(|i| i.smooth_scroll_delta.y);
+            let offset = camera.pos - self.target;
+            if scroll != 0.0 && offset != Vector3::zeros() {
+                let distance = (offset.norm() * (-scroll * 0.002).exp()).max(MIN_DISTANCE);
+                camera.pos = self.target + offset.normalize() * distance;
+            }
+        }
+    }
+
+    /// Turns the camera for a drag of `drag` points.
+    fn drag(&self, drag: egui::Vec2, camera: &mut Camera) {
+        match self.mode {
+            CameraMode::Fly => {
+                let per_point = FLY_DEGREES_PER_POINT.to_radians();
+                if drag != egui::Vec2::ZERO {
+                    camera.rotate_local(-drag.y * per_point, -drag.x * per_point, 0.0);
+                }
+            }
+            CameraMode::Orbit => {
+                let per_point = ORBIT_DEGREES_PER_POINT.to_radians();
+                if drag.x != 0.0 {
+                    camera.rotate_about(self.target, UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -drag.x * per_point));
+                }
+                if drag.y != 0.0 {
+                    let tilt = UnitQuaternion::from_scaled_axis(camera.right() * -drag.y * per_point);
+                    // past the pole the orbit would flip the view over, and a long drag can
+                    // step clean across it, so the heading must not reverse either
+                    let (look, tilted) = (camera.look(), tilt * camera.look());
+                    let heading = look.x * tilted.x + look.z * tilted.z;
+                    if tilted.y.abs() < MAX_PITCH && heading > 0.0 {
+                        camera.rotate_about(self.target, tilt);
+                    }
+                }
+            }
+            CameraMode::Trackball => {
+                let per_point = ORBIT_DEGREES_PER_POINT.to_radians();
+                // the camera circles the opposite way the surface under the cursor should move
+                let axis = camera.get_up_vec() * -drag.x + camera.right() * -drag.y;
+                if axis != Vector3::zeros() {
+                    camera.rotate_about(self.target, UnitQuaternion::from_scaled_axis(axis * per_point));
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orbiting(mode: CameraMode) -> (CameraControls, Camera) {
+        let controls = CameraControls { mode, target: Vector3::new(0.0, 0.5, 0.0) };
+        let mut camera = Camera { pos: Vector3::new(0.0, 0.5, 3.0), ..Camera::default() };
+        camera.look_at(controls.target, Vector3::y());
+        (controls, camera)
+    }
+
+    #[test]
+    fn orbit_stops_short_of_the_poles() {
+        let (controls, mut camera) = orbiting(CameraMode::Orbit);
+        for drag in [egui::vec2(0.0, 40.0), egui::vec2(0.0, -40.0)] {
+            for _ in 0..100 {
+                controls.drag(drag, &mut camera);
+                assert!(camera.look().y.abs() < MAX_PITCH, "{}", camera.look());
+            }
+            // tilted as far as it goes, and still level
+            assert!(camera.look().y.abs() > 0.9, "{}", camera.look());
+            assert!(camera.right().y.abs() < 1.0e-4);
+        }
+    }
+
+    #[test]
+    fn orbit_and_trackball_keep_the_distance_to_the_target() {
+        for mode in [CameraMode::Orbit, CameraMode::Trackball] {
+            let (controls, mut camera) = orbiting(mode);
+            for drag in [egui::vec2(25.0, 10.0), egui::vec2(-7.0, 33.0), egui::vec2(90.0, -60.0)] {
+                controls.drag(drag, &mut camera);
+                assert!(((camera.pos - controls.target).norm() - 3.0).abs() < 1.0e-4);
+                assert!((camera.look() - (controls.target - camera.pos).normalize()).norm() < 1.0e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn fly_turns_in_place() {
+        let (controls, mut camera) = orbiting(CameraMode::Fly);
+        let pos = camera.pos;
+        controls.drag(egui::vec2(30.0, 0.0), &mut camera);
+        assert_eq!(camera.pos, pos);
+        assert!(camera.look().x > 0.0);
+    }
+
+    #[test]
+    fn switching_to_orbit_faces_the_target() {
+        let mut controls = CameraControls { target: Vector3::new(1.0, 0.0, 0.0), ..CameraControls::default() };
+        let mut camera = Camera::default();
+        controls.set_mode(CameraMode::Orbit, &mut camera);
+        assert!((camera.look() - (controls.target - camera.pos).normalize()).norm() < 1.0e-5);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{ops::Range, path::{Path, PathBuf}};
+
+use clap::Parser;
+use meshview::{mesh_export::{self, MeshError, MeshFormat, MeshSettings}, render, scene::Scene};
+use nalgebra::Vector3;
+
+
+/// Raymarcher. Opens the viewer window unless `--output` or `--mesh` is given, in which case
+/// frames or a mesh are made on the CPU and written to disk.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Args {
+    /// Output PNG path. When rendering a range of frames the frame number is appended,
+    /// e.g. `out.png` becomes `out_0001.png`
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Export a triangle mesh of the distance field instead of rendering. The format follows
+    /// the extension: `.obj`, `.stl` (binary) or `.ply` (binary)
+    #[arg(long)]
+    pub mesh: Option<PathBuf>,
+
+    /// Corner of the mesh bounding box as `x,y,z`
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true, default_value = "-1.5,-1.5,-1.5")]
+    pub bounds_min: Vector3<f32>,
+
+    /// Opposite corner of the mesh bounding box as `x,y,z`
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true, default_value = "1.5,1.5,1.5")]
+    pub bounds_max: Vector3<f32>,
+
+    /// Grid cells along the longest side of the mesh bounding box
+    #[arg(long, default_value_t = 128)]
+    pub resolution: u32,
+
+    /// Scene file to start from. Any of the flags below override its values
+    #[arg(long)]
+    pub scene: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 420, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: u32,
+
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: u32,
+
+    /// Camera position as `x,y,z` [default: 0,0,3]
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub pos: Option<Vector3<f32>>,
+
+    /// Camera rotation in degrees as `x,y,z` [default: 0,0,0]
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub rot: Option<Vector3<f32>>,
+
+    /// Mandelbulb exponent [default: 8]
+    #[arg(long)]
+    pub exp: Option<f32>,
+
+    /// [default: 12]
+    #[arg(long)]
+    pub iterations: Option<u32>,
+
+    /// At least 1 [default: 1]
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub detail: Option<i32>,
+
+    /// Render an animation range `start..end` (end exclusive) instead of a single frame.
+    /// Parameters with keyframes on the scene's timeline override the flags above
+    #[arg(long, value_parser = parse_range)]
+    pub frames: Option<Range<u32>>,
+
+    /// Frames per second used to turn frame numbers into animation time
+    #[arg(long, default_value_t = 30.0, value_parser = parse_fps)]
+    pub fps: f32,
+
+    /// Load `main.vert.glsl` and `main.frag.glsl` from this directory instead of the copies
+    /// built into the binary, and reload them whenever they change
+    #[arg(long, env = "RAYMARCHER_SHADER_DIR")]
+    pub shader_dir: Option<PathBuf>,
+}
+
+
+pub fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = scene(args)?;
+
+    if let Some(path) = &args.mesh {
+        // before the slow part
+        MeshFormat::from_path(path).ok_or_else(|| MeshError::UnknownFormat(path.display().to_string()))?;
+        let settings = MeshSettings { min: args.bounds_min, max: args.bounds_max, resolution: args.resolution };
+        let mesh = mesh_export::extract(&scene.render_settings(args.width, args.height), &settings)?;
+        mesh.save(path)?;
+        println!("wrote {} ({} triangles)", path.display(), mesh.triangles.len());
+        if args.output.is_none() {
+            return Ok(());
+        }
+    }
+
+    let output = args.output.as_ref().ok_or("no output path given")?;
+    render_frames(args, &scene, output)
+}
+
+
+/// The `--scene` file, or the default scene, with the flags applied.
+fn scene(args: &Args) -> Result<Scene, Box<dyn std::error::Error>> {
+    let mut scene = match &args.scene {
+        Some(path) => Scene::load(path)?,
+        None => Scene::default(),
+    };
+
+    if let Some(pos) = args.pos {
+        scene.position = pos;
+    }
+    if let Some(rot) = args.rot {
+        scene.angle = (rot.x, rot.y, rot.z);
+        scene.orientation = None;
+    }
+    if let Some(exp) = args.exp {
+        scene.exp = exp;
+    }
+    if let Some(iterations) = args.iterations {
+        scene.num_iters = iterations;
+    }
+    if let Some(detail) = args.detail {
+        scene.detail = detail;
+    }
+    Ok(scene)
+}
+
+
+fn render_frames(args: &Args, scene: &Scene, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.frames {
+        None => {
+            let camera = scene.camera();
+            let settings = scene.render_settings(args.width, args.height);
+
+            let img = render::render(&camera, &settings)?;
+            render::save_png(&img, output)?;
+            println!("wrote {}", output.display());
+        }
+        Some(frames) => {
+            for frame in frames.clone() {
+                let mut frame_scene = scene.clone();
+                frame_scene.apply_sample(&scene.timeline.sample(frame as f32 / args.fps));
+
+                let camera = frame_scene.camera();
+                let settings = frame_scene.render_settings(args.width, args.height);
+
+                let path = frame_path(output, frame);
+                let img = render::render(&camera, &settings)?;
+                render::save_png(&img, &path)?;
+                println!("wrote {}", path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+fn frame_path(output: &Path, frame: u32) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = output.extension().map_or("png".into(), |e| e.to_string_lossy());
+    output.with_file_name(format!("{stem}_{frame:04}.{ext}"))
+}
+
+fn parse_vec3(s: &str) -> Result<Vector3<f32>, String> {
+    let parts = s
+        .split(',')
+        .map(|x| x.trim().parse::<f32>().map_err(|e| format!("'{x}': {e}")))
+        .collect::<Result<Vec<f32>, String>>()?;
+
+    match parts[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => Err(format!("expected 3 comma separated values, got {}", parts.len())),
+    }
+}
+
+fn parse_fps(s: &str) -> Result<f32, String> {
+    let fps = s.trim().parse::<f32>().map_err(|e| e.to_string())?;
+    if !(fps.is_finite() && fps > 0.0) {
+        return Err(format!("{fps} is not a positive frame rate"));
+    }
+    Ok(fps)
+}
+
+fn parse_range(s: &str) -> Result<Range<u32>, String> {
+    let (start, end) = s.split_once("..").ok_or("expected a range like 0..120")?;
+    let start = start.trim().parse::<u32>().map_err(|e| e.to_string())?;
+    let end = end.trim().parse::<u32>().map_err(|e| e.to_string())?;
+
+    if end <= start {
+        return Err(format!("empty frame range {start}..{end}"));
+    }
+    Ok(start..end)
+}
@@ -0,0 +1,202 @@
+use std::f32::consts::TAU;
+
+use eframe::glow::{self, HasContext as _};
+use meshview::camera::Camera;
+use nalgebra::{Matrix4, UnitQuaternion, Vector2, Vector3};
+
+use crate::{error::GlError, mesh::Mesh, shader::ShaderProgram, uniforms::UniformRegistry};
+
+// Line overlays for keeping track of orientation and scale while flying: the world axes,
+// a ground grid and the mandelbulb's bailout sphere are drawn into the scene and tested
+// against the fractal's depth. The camera's right/up/look vectors are drawn next to the
+// world axes in an inset in the corner, seen from a fixed point outside the scene.
+// All of it goes through `Mesh`'s wireframe mode, with a segment a-b stored as the
+// degenerate triangle a-b-b.
+
+
+const OVERLAY_VERT: &str = include_str!("overlay.vert.glsl");
+const OVERLAY_FRAG: &str = include_str!("overlay.frag.glsl");
+
+/// Length of the world axes drawn in the scene.
+const AXIS_LENGTH: f32 = 3.0;
+/// Radius past which the mandelbulb iteration escapes.
+const BAILOUT_RADIUS: f32 = 2.0;
+const GRID_HEIGHT: f32 = -BAILOUT_RADIUS;
+const GRID_EXTENT: f32 = 4.0;
+const GRID_SPACING: f32 = 0.5;
+/// Side of the corner inset, in points.
+const INSET_SIZE: f32 = 96.0;
+
+const AXIS_COLORS: [Vector3<f32>; 3] = [
+    Vector3::new(0.9, 0.2, 0.2),
+    Vector3::new(0.2, 0.85, 0.2),
+    Vector3::new(0.3, 0.4, 1.0),
+];
+const GRID_COLOR: Vector3<f32> = Vector3::new(0.45, 0.45, 0.5);
+const SPHERE_COLOR: Vector3<f32> = Vector3::new(1.0, 0.75, 0.3);
+/// Right, up and look
+const CAMERA_COLORS: [Vector3<f32>; 3] = [
+    Vector3::new(1.0, 0.45, 0.8),
+    Vector3::new(0.95, 0.95, 0.35),
+    Vector3::new(1.0, 1.0, 1.0),
+];
+
+
+/// Which overlays are shown.
+#[derive(Clone, Copy, Debug)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    pub axes: bool,
+    pub grid: bool,
+    pub bailout_sphere: bool,
+    pub camera_vectors: bool,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            axes: true,
+            grid: true,
+            bailout_sphere: true,
+            camera_vectors: true,
+        }
+    }
+}
+
+
+fn line_mesh(gl: &glow::Context, segments: &[(Vector3<f32>, Vector3<f32>)]) -> Result<Mesh, GlError> {
+    let positions: Vec<_> = segments.iter().flat_map(|&(a, b)| [a, b]).collect();
+    let indices = (0..segments.len() as u32).flat_map(|i| [2 * i, 2 * i + 1, 2 * i + 1]).collect();
+    let uvs = vec![Vector2::zeros(); positions.len()];
+    Mesh::new(gl, positions, indices, uvs, true)
+}
+
+fn grid_segments() -> Vec<(Vector3<f32>, Vector3<f32>)> {
+    let lines = (2.0 * GRID_EXTENT / GRID_SPACING).round() as i32;
+    (0..=lines)
+        .flat_map(|i| {
+            let x = -GRID_EXTENT + i as f32 * GRID_SPACING;
+            [
+                (Vector3::new(x, GRID_HEIGHT, -GRID_EXTENT), Vector3::new(x, GRID_HEIGHT, GRID_EXTENT)),
+                (Vector3::new(-GRID_EXTENT, GRID_HEIGHT, x), Vector3::new(GRID_EXTENT, GRID_HEIGHT, x)),
+            ]
+        })
+        .collect()
+}
+
+/// Latitude circles and meridians of a sphere around the origin.
+fn sphere_segments(radius: f32) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+    const RINGS: usize = 8;
+    const MERIDIANS: usize = 12;
+    const STEPS: usize = 48;
+
+    let point = |theta: f32, phi: f32| radius * Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+    let mut segments = Vec::new();
+    for ring in 1..RINGS {
+        let theta = ring as f32 / RINGS as f32 * TAU / 2.0;
+        for step in 0..STEPS {
+            let phi = |s: usize| s as f32 / STEPS as f32 * TAU;
+            segments.push((point(theta, phi(step)), point(theta, phi(step + 1))));
+        }
+    }
+    for meridian in 0..MERIDIANS {
+        let phi = meridian as f32 / MERIDIANS as f32 * TAU;
+        for step in 0..STEPS / 2 {
+            let theta = |s: usize| s as f32 / STEPS as f32 * TAU;
+            segments.push((point(theta(step), phi), point(theta(step + 1), phi)));
+        }
+    }
+    segments
+}
+
+/// Model matrix taking the unit segment along +x to `from..from + vector`.
+fn segment_transform(from: Vector3<f32>, vector: Vector3<f32>) -> Matrix4<f32> {
+    let mut m = Matrix4::zeros();
+    m.fixed_view_mut::<3, 1>(0, 0).copy_from(&vector);
+    m.fixed_view_mut::<3, 1>(0, 3).copy_from(&from);
+    m[(3, 3)] = 1.0;
+    m
+}
+
+
+/// GL side of the overlays, created in the paint callback on first use.
+pub struct DebugLayer {
+    program: ShaderProgram,
+    unit_line: Mesh,
+    grid: Mesh,
+    sphere: Mesh,
+}
+
+impl DebugLayer {
+    pub fn new(gl: &glow::Context) -> Result<Self, GlError> {
+        Ok(Self {
+            program: ShaderProgram::new(gl, OVERLAY_VERT, OVERLAY_FRAG)?,
+            unit_line: line_mesh(gl, &[(Vector3::zeros(), Vector3::x())])?,
+            grid: line_mesh(gl, &grid_segments())?,
+            sphere: line_mesh(gl, &sphere_segments(BAILOUT_RADIUS))?,
+        })
+    }
+
+    fn line(&self, gl: &glow::Context, view_proj: Matrix4<f32>, model: Matrix4<f32>, mesh: &Mesh, color: Vector3<f32>, depth_tested: bool) {
+        let set_uniforms = |gl: &glow::Context, uniforms: &UniformRegistry| {
+            uniforms.set(gl, "u_ViewProj", view_proj);
+            uniforms.set(gl, "u_Model", model);
+            uniforms.set(gl, "u_Color", color);
+        };
+        if depth_tested {
+            self.program.draw_over(gl, mesh, set_uniforms);
+        } else {
+            self.program.draw(gl, mesh, set_uniforms);
+        }
+    }
+
+    /// Draws the scene overlays into the bound framebuffer, against the depth in it, then
+    /// the camera inset in the bottom left corner of `viewport` (x, y, width, height in
+    /// pixels). The inset clears depth, so this goes after everything else in the scene.
+    pub fn draw(&self, gl: &glow::Context, overlay: &DebugOverlay, camera: &Camera, viewport: [i32; 4], pixels_per_point: f32) {
+        let view_proj = camera.get_proj_view_mat();
+        if overlay.grid {
+            self.line(gl, view_proj, Matrix4::identity(), &self.grid, GRID_COLOR, true);
+        }
+        if overlay.bailout_sphere {
+            self.line(gl, view_proj, Matrix4::identity(), &self.sphere, SPHERE_COLOR, true);
+        }
+        if overlay.axes {
+            for (axis, color) in AXIS_COLORS.into_iter().enumerate() {
+                let model = segment_transform(Vector3::zeros(), AXIS_LENGTH * Vector3::ith(axis, 1.0));
+                self.line(gl, view_proj, model, &self.unit_line, color, true);
+            }
+        }
+
+        if overlay.camera_vectors {
+            let [x, y, width, height] = viewport;
+            let size = ((INSET_SIZE * pixels_per_point) as i32).min(width).min(height);
+            unsafe {
+                gl.viewport(x, y, size, size);
+            }
+
+            // looking at the origin from the +x +y +z octant
+            let mut inset = Camera::new(Vector3::new(1.3, 0.9, 1.5), UnitQuaternion::identity(), camera.fov, 1.0);
+            inset.look_at(Vector3::zeros(), Vector3::y());
+            let inset_proj = inset.get_proj_view_mat();
+
+            for (axis, color) in AXIS_COLORS.into_iter().enumerate() {
+                let model = segment_transform(Vector3::zeros(), Vector3::ith(axis, 1.0));
+                self.line(gl, inset_proj, model, &self.unit_line, color * 0.6, false);
+            }
+            let vectors = [camera.right(), camera.get_up_vec(), camera.look()];
+            for (vector, color) in vectors.into_iter().zip(CAMERA_COLORS) {
+                self.line(gl, inset_proj, segment_transform(Vector3::zeros(), vector), &self.unit_line, color, false);
+            }
+
+            unsafe {
+                gl.viewport(x, y, width, height);
+            }
+        }
+
+        unsafe {
+            gl.disable(glow::DEPTH_TEST);
+        }
+    }
+}
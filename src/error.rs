@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use meshview::sdf_graph::GraphError;
+
+
+/// Everything that can go wrong while setting up GL objects.
+#[derive(Debug)]
+pub enum GlError {
+    Io { path: PathBuf, source: std::io::Error },
+    /// `stage` is "vertex" or "fragment"
+    Compile { stage: &'static str, log: String },
+    Link { log: String },
+    /// The driver refused to create a buffer, texture, program, ...
+    Resource { object: &'static str, message: String },
+    /// The scene graph can't be turned into the shader's `map()`
+    Graph(GraphError),
+}
+
+
+impl GlError {
+    /// Adapter for glow's `create_*` calls, which report failure as a bare string.
+    pub fn resource(object: &'static str) -> impl FnOnce(String) -> Self {
+        move |message| Self::Resource { object, message }
+    }
+}
+
+
+impl std::fmt::Display for GlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "could not read {}: {source}", path.display()),
+            Self::Compile { stage, log } => write!(f, "{stage} shader failed to compile: {}", log.trim_end()),
+            Self::Link { log } => write!(f, "shader program failed to link: {}", log.trim_end()),
+            Self::Resource { object, message } => write!(f, "could not create {object}: {message}"),
+            Self::Graph(err) => write!(f, "invalid scene graph: {err}"),
+        }
+    }
+}
+
+
+impl std::error::Error for GlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Graph(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::{ops::Range, path::PathBuf};
+
+use meshview::{animation::Timeline, camera::Camera};
+
+
+/// An image-sequence export in progress. One frame is rendered per UI frame, with the
+/// animation clock set from the frame number instead of wall time. The job only moves on
+/// once the paint callback reports the current frame written, so frames are never skipped
+/// or repeated however the UI and paint timing line up.
+pub struct ExportJob {
+    pub dir: PathBuf,
+    pub fps: f32,
+    pub width: u32,
+    pub height: u32,
+    pub frames: Range<u32>,
+    pub next: u32,
+    /// Camera when the export started, for whatever the timeline has no keys for
+    pub camera: Camera,
+}
+
+
+impl ExportJob {
+    pub fn new(dir: PathBuf, fps: f32, width: u32, height: u32, frames: Range<u32>, camera: Camera) -> Self {
+        Self {
+            dir,
+            fps,
+            width,
+            height,
+            next: frames.start,
+            frames,
+            camera,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.next as f32 / self.fps
+    }
+
+    /// Camera for the current frame: the timeline's camera keys over the starting camera.
+    /// The live camera isn't used, as input or a bookmark flight could move it mid-export.
+    pub fn frame_camera(&self, timeline: &Timeline) -> Camera {
+        let mut camera = self.camera.clone();
+        timeline.sample(self.time()).apply_camera(&mut camera);
+        camera.aspect_ratio = self.width as f32 / self.height as f32;
+        camera
+    }
+
+    /// Called with the frame the paint callback wrote. Stale reports are ignored.
+    pub fn advance(&mut self, written: u32) {
+        if written == self.next {
+            self.next += 1;
+        }
+    }
+
+    pub fn frame_path(&self) -> PathBuf {
+        self.dir.join(format!("frame_{:04}.png", self.next))
+    }
+
+    pub fn progress(&self) -> f32 {
+        (self.next - self.frames.start) as f32 / self.frames.len().max(1) as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.frames.end
+    }
+}
@@ -0,0 +1,59 @@
+use eframe::egui::{self, DragValue};
+use meshview::sdf::{FractalParams, FractalType};
+
+use crate::widgets::{slider, vec3};
+
+
+/// Controls for the parameter set of `kind`. The mandelbulb only uses `Exp`, which lives
+/// with the other shared settings.
+pub fn fractal_params_ui(ui: &mut egui::Ui, kind: FractalType, params: &mut FractalParams) {
+    match kind {
+        FractalType::Mandelbulb => {}
+        FractalType::Mandelbox => {
+            let p = &mut params.mandelbox;
+            slider(ui, "Scale", &mut p.scale, -3.0..=3.0);
+            slider(ui, "Min radius", &mut p.min_radius, 0.0..=2.0);
+            slider(ui, "Fixed radius", &mut p.fixed_radius, 0.0..=2.0);
+            slider(ui, "Folding limit", &mut p.folding_limit, 0.0..=2.0);
+        }
+        FractalType::Menger => {
+            let p = &mut params.menger;
+            slider(ui, "Scale", &mut p.scale, 1.0..=4.0);
+            vec3(ui, "Offset", &mut p.offset);
+        }
+        FractalType::Sierpinski => {
+            let p = &mut params.sierpinski;
+            slider(ui, "Scale", &mut p.scale, 1.0..=3.0);
+            slider(ui, "Offset", &mut p.offset, 0.0..=2.0);
+        }
+        FractalType::QuaternionJulia => {
+            let p = &mut params.julia;
+            ui.horizontal(|ui| {
+                ui.label("C");
+                ui.add(DragValue::new(&mut p.c.x).speed(0.005));
+                ui.add(DragValue::new(&mut p.c.y).speed(0.005));
+                ui.add(DragValue::new(&mut p.c.z).speed(0.005));
+                ui.add(DragValue::new(&mut p.c.w).speed(0.005));
+            });
+            slider(ui, "Slice (w)", &mut p.slice, -1.0..=1.0);
+        }
+        FractalType::Kleinian => {
+            let p = &mut params.kleinian;
+            // the fold clamps to ±box size
+            ui.horizontal(|ui| {
+                ui.label("Box size");
+                for size in p.box_size.iter_mut() {
+                    ui.add(DragValue::new(size).speed(0.01).range(0.0..=4.0));
+                }
+            });
+            slider(ui, "Sphere size", &mut p.sphere_size, 0.1..=2.0);
+            slider(ui, "Thickness", &mut p.thickness, 0.0..=2.0);
+            slider(ui, "Bounds", &mut p.bounding_radius, 0.1..=10.0);
+        }
+        FractalType::Apollonian => {
+            let p = &mut params.apollonian;
+            slider(ui, "Scale", &mut p.scale, 0.5..=2.0);
+            slider(ui, "Bounds", &mut p.bounding_radius, 0.1..=10.0);
+        }
+    }
+}
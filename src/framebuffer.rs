@@ -0,0 +1,134 @@
+use eframe::glow::{self, HasContext as _};
+
+use crate::error::GlError;
+
+
+/// Storage of the color attachment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorFormat {
+    /// 8 bits per channel, what gets saved to images
+    Rgba8,
+    /// 32-bit float per channel, for sums that need more precision than 8 bits
+    Rgba32F,
+}
+
+
+/// Offscreen render target with a color texture and a depth texture.
+pub struct Framebuffer {
+    pub fbo: glow::Framebuffer,
+    pub color_texture: glow::Texture,
+    pub depth_texture: glow::Texture,
+    pub format: ColorFormat,
+    pub width: i32,
+    pub height: i32,
+}
+
+
+impl Framebuffer {
+    pub fn new(gl: &glow::Context, width: i32, height: i32, format: ColorFormat) -> Result<Self, GlError> {
+        unsafe {
+            let fbo = gl.create_framebuffer().map_err(GlError::resource("framebuffer"))?;
+            let color_texture = gl.create_texture().map_err(GlError::resource("framebuffer texture"))?;
+            let depth_texture = gl.create_texture().map_err(GlError::resource("depth texture"))?;
+
+            let mut x = Self {
+                fbo,
+                color_texture,
+                depth_texture,
+                format,
+                width,
+                height
+            };
+
+            if let Err(err) = x.allocate(gl) {
+                x.destroy(gl);
+                return Err(err);
+            }
+
+            Ok(x)
+        }
+    }
+
+    fn allocate(&mut self, gl: &glow::Context) -> Result<(), GlError> {
+        unsafe {
+            let (internal_format, data_type) = match self.format {
+                ColorFormat::Rgba8 => (glow::RGBA8, glow::UNSIGNED_BYTE),
+                ColorFormat::Rgba32F => (glow::RGBA32F, glow::FLOAT),
+            };
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.color_texture));
+            gl.tex_image_2d(glow::TEXTURE_2D, 0, internal_format as i32, self.width, self.height, 0, glow::RGBA, data_type, None);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            // a texture rather than a renderbuffer so the depth can be copied out with the color
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.depth_texture));
+            gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::DEPTH_COMPONENT24 as i32, self.width, self.height, 0, glow::DEPTH_COMPONENT, glow::UNSIGNED_INT, None);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
+            gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(self.color_texture), 0);
+            gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::TEXTURE_2D, Some(self.depth_texture), 0);
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                return Err(GlError::Resource {
+                    object: "offscreen framebuffer",
+                    message: format!("incomplete (status 0x{status:x})"),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reallocates the attachments if the size changed. Returns whether it did, which
+    /// leaves the contents undefined.
+    pub fn resize(&mut self, gl: &glow::Context, width: i32, height: i32) -> Result<bool, GlError> {
+        if (width, height) == (self.width, self.height) {
+            return Ok(false);
+        }
+        self.width = width;
+        self.height = height;
+        self.allocate(gl)?;
+        Ok(true)
+    }
+
+    /// Binds the framebuffer and sets the viewport to cover all of it.
+    pub fn bind(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
+            gl.viewport(0, 0, self.width, self.height);
+        }
+    }
+
+    /// Reads back the color attachment as tightly packed RGBA8, top row first. Float
+    /// attachments are clamped and quantized by GL.
+    pub fn read_rgba(&self, gl: &glow::Context) -> Vec<u8> {
+        let row = self.width as usize * 4;
+        let mut pixels = vec![0u8; row * self.height as usize];
+
+        unsafe {
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.fbo));
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            gl.read_pixels(0, 0, self.width, self.height, glow::RGBA, glow::UNSIGNED_BYTE, glow::PixelPackData::Slice(&mut pixels));
+        }
+
+        // GL rows start at the bottom
+        pixels.chunks_exact(row).rev().flatten().copied().collect()
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.fbo);
+            gl.delete_texture(self.color_texture);
+            gl.delete_texture(self.depth_texture);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use eframe::egui::{self, DragValue};
+use meshview::sdf_graph::SdfNode;
+use nalgebra::{UnitQuaternion, Vector3};
+
+use crate::widgets::vec3;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NewPrimitive {
+    Sphere,
+    Box,
+    Torus,
+    Capsule,
+    Plane,
+}
+
+impl NewPrimitive {
+    const ALL: [NewPrimitive; 5] = [NewPrimitive::Sphere, NewPrimitive::Box, NewPrimitive::Torus, NewPrimitive::Capsule, NewPrimitive::Plane];
+
+    fn node(&self) -> SdfNode {
+        match self {
+            NewPrimitive::Sphere => SdfNode::Sphere { radius: 0.5 },
+            NewPrimitive::Box => SdfNode::Box { half_extents: Vector3::new(0.5, 0.5, 0.5) },
+            NewPrimitive::Torus => SdfNode::Torus { major_radius: 1.2, minor_radius: 0.1 },
+            NewPrimitive::Capsule => SdfNode::Capsule { a: Vector3::new(0.0, -0.5, 0.0), b: Vector3::new(0.0, 0.5, 0.0), radius: 0.2 },
+            NewPrimitive::Plane => SdfNode::Plane { normal: Vector3::new(0.0, 1.0, 0.0), offset: -1.2 },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NewOp {
+    Union,
+    SmoothUnion,
+    Subtraction,
+    Intersection,
+}
+
+impl NewOp {
+    const ALL: [NewOp; 4] = [NewOp::Union, NewOp::SmoothUnion, NewOp::Subtraction, NewOp::Intersection];
+}
+
+
+/// Editor for the scene's SDF graph. New primitives are combined with the current root.
+pub struct GraphEditor {
+    primitive: NewPrimitive,
+    op: NewOp,
+}
+
+impl Default for GraphEditor {
+    fn default() -> Self {
+        Self { primitive: NewPrimitive::Sphere, op: NewOp::Union }
+    }
+}
+
+impl GraphEditor {
+    pub fn show(&mut self, ui: &mut egui::Ui, graph: &mut SdfNode) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("graph_op")
+                .selected_text(format!("{:?}", self.op))
+                .show_ui(ui, |ui| {
+                    for op in NewOp::ALL {
+                        ui.selectable_value(&mut self.op, op, format!("{op:?}"));
+                    }
+                });
+            egui::ComboBox::from_id_salt("graph_primitive")
+                .selected_text(format!("{:?}", self.primitive))
+                .show_ui(ui, |ui| {
+                    for primitive in NewPrimitive::ALL {
+                        ui.selectable_value(&mut self.primitive, primitive, format!("{primitive:?}"));
+                    }
+                });
+
+            if ui.button("Add").clicked() {
+                let root = std::mem::take(graph);
+                let node = self.primitive.node().translated(Vector3::zeros());
+                *graph = match self.op {
+                    NewOp::Union => SdfNode::union(root, node),
+                    NewOp::SmoothUnion => SdfNode::smooth_union(root, node, 0.1),
+                    NewOp::Subtraction => SdfNode::subtraction(root, node),
+                    NewOp::Intersection => SdfNode::intersection(root, node),
+                };
+            }
+            if ui.button("Reset").clicked() {
+                *graph = SdfNode::Fractal;
+            }
+        });
+
+        node_ui(ui, graph, egui::Id::new("sdf_graph"));
+    }
+}
+
+
+fn f32_ui(ui: &mut egui::Ui, label: &str, x: &mut f32) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(DragValue::new(x).speed(0.01));
+    });
+}
+
+fn node_ui(ui: &mut egui::Ui, node: &mut SdfNode, id: egui::Id) {
+    let title = node.name();
+
+    if let SdfNode::Fractal = node {
+        ui.label(title);
+        return;
+    }
+
+    egui::CollapsingHeader::new(title).id_salt(id).default_open(true).show(ui, |ui| {
+        match node {
+            SdfNode::Fractal => {}
+            SdfNode::Sphere { radius } => f32_ui(ui, "Radius", radius),
+            SdfNode::Box { half_extents } => vec3(ui, "Half extents", half_extents),
+            SdfNode::Torus { major_radius, minor_radius } => {
+                f32_ui(ui, "Major radius", major_radius);
+                f32_ui(ui, "Minor radius", minor_radius);
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                vec3(ui, "A", a);
+                vec3(ui, "B", b);
+                f32_ui(ui, "Radius", radius);
+            }
+            SdfNode::Plane { normal, offset } => {
+                vec3(ui, "Normal", normal);
+                f32_ui(ui, "Offset", offset);
+            }
+            SdfNode::Union(a, b) | SdfNode::Intersection(a, b) | SdfNode::Subtraction(a, b) => {
+                node_ui(ui, a, id.with(0));
+                node_ui(ui, b, id.with(1));
+            }
+            SdfNode::SmoothUnion { a, b, k } => {
+                // smin divides by k
+                ui.horizontal(|ui| {
+                    ui.label("Smoothness");
+                    ui.add(DragValue::new(k).speed(0.01).range(0.001..=10.0));
+                });
+                node_ui(ui, a, id.with(0));
+                node_ui(ui, b, id.with(1));
+            }
+            SdfNode::Transform { translation, rotation, scale, child } => {
+                vec3(ui, "Translation", translation);
+
+                let (x, y, z) = rotation.euler_angles();
+                let mut angles = Vector3::new(x.to_degrees(), y.to_degrees(), z.to_degrees());
+                let before = angles;
+                vec3(ui, "Rotation", &mut angles);
+                if angles != before {
+                    *rotation = UnitQuaternion::from_euler_angles(angles.x.to_radians(), angles.y.to_radians(), angles.z.to_radians());
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Scale");
+                    ui.add(DragValue::new(scale).speed(0.01).range(0.01..=100.0));
+                });
+                node_ui(ui, child, id.with(0));
+            }
+        }
+    });
+}
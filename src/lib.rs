@@ -0,0 +1,12 @@
+pub mod animation;
+pub mod bookmark;
+pub mod camera;
+pub mod lighting;
+pub mod mesh_export;
+pub mod orbit_trap;
+pub mod palette;
+pub mod render;
+pub mod scene;
+pub mod sdf;
+pub mod sdf_graph;
+pub mod shader_params;
@@ -0,0 +1,203 @@
+use nalgebra::{Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+// Lighting shared by the shader and the CPU renderer. `shade` mirrors `shade()` in
+// main.frag.glsl step for step.
+
+/// Size of the light uniform arrays in the shader.
+pub const MAX_LIGHTS: usize = 4;
+
+/// How far a shadow ray toward a directional light is marched.
+const SHADOW_DISTANCE: f32 = 20.0;
+const SHADOW_STEPS: u32 = 64;
+/// Shadow rays start this far off the surface so they do not hit it right away.
+const SHADOW_BIAS: f32 = 0.002;
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightKind {
+    /// Infinitely far away; `vector` points toward the light
+    Directional,
+    /// `vector` is the light's position. Falls off with distance
+    Point,
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub enabled: bool,
+    pub vector: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn directional(toward: Vector3<f32>) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            enabled: true,
+            vector: toward,
+            color: Vector3::repeat(1.0),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn point(position: Vector3<f32>) -> Self {
+        Self {
+            kind: LightKind::Point,
+            enabled: true,
+            vector: position,
+            color: Vector3::repeat(1.0),
+            intensity: 6.0,
+        }
+    }
+}
+
+
+/// Light rig and surface effects. With `enabled` off the surface color is used as is,
+/// which is the original flat look.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Lighting {
+    pub enabled: bool,
+    pub lights: Vec<Light>,
+    pub ambient: f32,
+    pub shadows: bool,
+    /// Higher is sharper
+    pub shadow_hardness: f32,
+    pub ambient_occlusion: bool,
+    pub ao_strength: f32,
+    pub specular: bool,
+    pub shininess: f32,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lights: vec![
+                Light::directional(Vector3::new(0.6, 0.8, 0.4)),
+                Light { color: Vector3::new(0.6, 0.7, 1.0), enabled: false, ..Light::point(Vector3::new(-2.0, 1.0, 2.0)) },
+            ],
+            ambient: 0.25,
+            shadows: true,
+            shadow_hardness: 16.0,
+            ambient_occlusion: true,
+            ao_strength: 1.0,
+            specular: true,
+            shininess: 32.0,
+        }
+    }
+}
+
+
+/// Lights in the layout of `u_LightVector` / `u_LightColor`. Only enabled lights are
+/// packed; `w` of the vector is 0 for directional and 1 for point lights, and the color
+/// is premultiplied by the intensity.
+pub struct PackedLights {
+    pub count: i32,
+    pub vectors: [Vector4<f32>; MAX_LIGHTS],
+    pub colors: [Vector3<f32>; MAX_LIGHTS],
+}
+
+impl Lighting {
+    pub fn packed(&self) -> PackedLights {
+        let mut packed = PackedLights {
+            count: 0,
+            vectors: [Vector4::zeros(); MAX_LIGHTS],
+            colors: [Vector3::zeros(); MAX_LIGHTS],
+        };
+        for light in self.lights.iter().filter(|light| light.enabled).take(MAX_LIGHTS) {
+            let i = packed.count as usize;
+            let w = match light.kind {
+                LightKind::Directional => 0.0,
+                LightKind::Point => 1.0,
+            };
+            packed.vectors[i] = light.vector.push(w);
+            packed.colors[i] = light.color * light.intensity;
+            packed.count += 1;
+        }
+        packed
+    }
+
+    /// Lights `albedo` at `p`, where `n` is the surface normal and `rd` the view ray
+    /// direction. `map` is the scene distance.
+    pub fn shade(&self, albedo: Vector3<f32>, p: Vector3<f32>, n: Vector3<f32>, rd: Vector3<f32>, map: impl Fn(Vector3<f32>) -> f32) -> Vector3<f32> {
+        if !self.enabled {
+            return albedo;
+        }
+
+        let ao = if self.ambient_occlusion {
+            1.0 + (ambient_occlusion(p, n, &map) - 1.0) * self.ao_strength
+        } else {
+            1.0
+        };
+        let mut light = Vector3::repeat(self.ambient * ao);
+        let mut spec = Vector3::zeros();
+
+        let packed = self.packed();
+        for i in 0..packed.count as usize {
+            let v = packed.vectors[i];
+            let (l, max_t, attenuation) = if v.w == 0.0 {
+                (v.xyz().normalize(), SHADOW_DISTANCE, 1.0)
+            } else {
+                let to_light = v.xyz() - p;
+                let d = to_light.norm();
+                (to_light / d, d, 1.0 / (1.0 + d * d))
+            };
+
+            let diffuse = n.dot(&l).max(0.0);
+            if diffuse <= 0.0 {
+                continue;
+            }
+            let shadow = if self.shadows {
+                soft_shadow(p + n * SHADOW_BIAS, l, max_t, self.shadow_hardness, &map)
+            } else {
+                1.0
+            };
+            let c = packed.colors[i] * (attenuation * shadow);
+            light += c * diffuse;
+            if self.specular {
+                let h = (l - rd).normalize();
+                spec += c * n.dot(&h).max(0.0).powf(self.shininess);
+            }
+        }
+
+        albedo.component_mul(&light) + spec
+    }
+}
+
+
+/// Penumbra estimate from the closest miss along the shadow ray, after Quilez. 0 is fully
+/// shadowed.
+pub fn soft_shadow(ro: Vector3<f32>, rd: Vector3<f32>, max_t: f32, hardness: f32, map: impl Fn(Vector3<f32>) -> f32) -> f32 {
+    let mut res: f32 = 1.0;
+    let mut t = 0.01;
+    let mut i = 0;
+    while i < SHADOW_STEPS && t < max_t {
+        let h = map(ro + rd * t);
+        if h < 0.0005 {
+            return 0.0;
+        }
+        res = res.min(hardness * h / t);
+        t += h.clamp(0.005, 0.25);
+        i += 1;
+    }
+    res.clamp(0.0, 1.0)
+}
+
+
+/// Compares the distance field to the distance along the normal at a few points. 1 is
+/// unoccluded.
+pub fn ambient_occlusion(p: Vector3<f32>, n: Vector3<f32>, map: impl Fn(Vector3<f32>) -> f32) -> f32 {
+    let mut occ = 0.0;
+    let mut scale = 1.0;
+    for i in 0..5 {
+        let h = 0.01 + 0.12 * i as f32 / 4.0;
+        let d = map(p + n * h);
+        occ += (h - d) * scale;
+        scale *= 0.95;
+    }
+    (1.0 - 3.0 * occ).clamp(0.0, 1.0)
+}
@@ -0,0 +1,83 @@
+use std::ops::RangeInclusive;
+
+use eframe::egui::{self, DragValue};
+use meshview::lighting::{Light, LightKind, Lighting, MAX_LIGHTS};
+use nalgebra::Vector3;
+
+use crate::widgets::{slider, vec3};
+
+
+fn toggled_slider(ui: &mut egui::Ui, label: &str, on: &mut bool, value: &mut f32, range: RangeInclusive<f32>) {
+    ui.horizontal(|ui| {
+        ui.checkbox(on, label);
+        ui.add_enabled(*on, egui::Slider::new(value, range));
+    });
+}
+
+fn light_ui(ui: &mut egui::Ui, light: &mut Light) -> bool {
+    let mut remove = false;
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut light.enabled, "");
+        egui::ComboBox::from_id_salt("kind")
+            .width(96.0)
+            .selected_text(match light.kind {
+                LightKind::Directional => "Directional",
+                LightKind::Point => "Point",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut light.kind, LightKind::Directional, "Directional");
+                ui.selectable_value(&mut light.kind, LightKind::Point, "Point");
+            });
+
+        let mut rgb = [light.color.x, light.color.y, light.color.z];
+        ui.color_edit_button_rgb(&mut rgb);
+        light.color = Vector3::from(rgb);
+        ui.add(DragValue::new(&mut light.intensity).speed(0.05).range(0.0..=100.0));
+
+        remove = ui.small_button("x").clicked();
+    });
+    let label = match light.kind {
+        LightKind::Directional => "Toward",
+        LightKind::Point => "Position",
+    };
+    vec3(ui, label, &mut light.vector);
+    remove
+}
+
+
+/// Light list plus the shadow, occlusion and highlight settings.
+pub fn lighting_ui(ui: &mut egui::Ui, lighting: &mut Lighting) {
+    ui.checkbox(&mut lighting.enabled, "Enabled");
+
+    ui.add_enabled_ui(lighting.enabled, |ui| {
+        slider(ui, "Ambient", &mut lighting.ambient, 0.0..=1.0);
+        toggled_slider(ui, "Soft shadows", &mut lighting.shadows, &mut lighting.shadow_hardness, 1.0..=64.0);
+        toggled_slider(ui, "Occlusion", &mut lighting.ambient_occlusion, &mut lighting.ao_strength, 0.0..=1.0);
+        toggled_slider(ui, "Specular", &mut lighting.specular, &mut lighting.shininess, 1.0..=256.0);
+
+        let mut removed = None;
+        for (i, light) in lighting.lights.iter_mut().enumerate() {
+            ui.push_id(i, |ui| {
+                if light_ui(ui, light) {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            lighting.lights.remove(i);
+        }
+
+        let active = lighting.lights.iter().filter(|light| light.enabled).count();
+        ui.horizontal(|ui| {
+            if ui.button("Add directional").clicked() {
+                lighting.lights.push(Light::directional(Vector3::new(0.0, 1.0, 0.0)));
+            }
+            if ui.button("Add point").clicked() {
+                lighting.lights.push(Light::point(Vector3::new(0.0, 2.0, 2.0)));
+            }
+        });
+        if active > MAX_LIGHTS {
+            ui.label(format!("Only the first {MAX_LIGHTS} enabled lights are used"));
+        }
+    });
+}
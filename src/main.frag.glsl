@@ -6,9 +6,62 @@ in vec2 fs_uv;
 uniform mat4 u_ViewProj;
 uniform mat4 u_InvViewProj;
 uniform vec3 u_CamPos;
-uniform float u_Exp;
-uniform uint u_Iters;
-uniform int u_Detail;
+uniform vec2 u_Jitter;   // sub-pixel offset of this sample in NDC, see accumulation.rs
+uniform float u_Exp;     // @ui slider 0 30 default=8 when=u_Fractal==FRACTAL_MANDELBULB
+uniform uint u_Iters;    // @ui stepper 1 40 label=Iterations default=12
+uniform int u_Detail;    // @ui stepper 1 100 default=1
+uniform vec3 u_Palette[3];
+
+#define COLOR_NORMAL 0
+#define COLOR_ORBIT_TRAP 1
+#define COLOR_ITERATIONS 2
+#define COLOR_DISTANCE 3
+#define COLOR_TRAP_CHANNELS 4
+#define MAX_STOPS 8
+uniform int u_ColorMode;
+uniform int u_StopCount;                  // 0 selects the cosine gradient
+uniform vec4 u_GradientStops[MAX_STOPS];  // rgb, position in w, sorted
+uniform vec3 u_Cosine[4];                 // a + b * cos(2pi(c * t + d))
+uniform float u_ColorScale;
+uniform float u_ColorOffset;
+uniform int u_Fractal;
+uniform vec4 u_FractalParams[2];
+
+#define TRAP_SPHERE 0
+#define TRAP_POINT 1
+#define TRAP_PLANE 2
+#define TRAP_AXIS 3
+#define TRAP_CROSS 4
+#define TRAP_BOX 5
+#define MAX_TRAPS 4
+uniform int u_TrapCount;
+uniform int u_TrapShape[MAX_TRAPS];
+uniform int u_TrapChannel[MAX_TRAPS];     // component of the trap vec4: r, g, b, or brightness in w
+uniform vec4 u_TrapA[MAX_TRAPS];          // center or point, plus radius or plane offset in w
+uniform vec4 u_TrapB[MAX_TRAPS];          // axis direction or box half extents
+
+#define MAX_LIGHTS 4
+uniform bool u_Lighting;
+uniform int u_LightCount;
+uniform vec4 u_LightVector[MAX_LIGHTS];   // w: 0 directional (xyz toward the light), 1 point (xyz position)
+uniform vec3 u_LightColor[MAX_LIGHTS];    // premultiplied by intensity
+uniform float u_Ambient;
+uniform bool u_Shadows;
+uniform float u_ShadowHardness;
+uniform bool u_AO;
+uniform float u_AOStrength;
+uniform bool u_Specular;
+uniform float u_Shininess;
+
+#define MAX_STEPS 2000
+
+#define FRACTAL_MANDELBULB 0
+#define FRACTAL_MANDELBOX 1
+#define FRACTAL_MENGER 2
+#define FRACTAL_SIERPINSKI 3
+#define FRACTAL_JULIA 4
+#define FRACTAL_KLEINIAN 5
+#define FRACTAL_APOLLONIAN 6
 
 out vec4 frag_color;
 
@@ -32,6 +85,66 @@ float sdBox( vec3 p, vec3 b )
   return length(max(q,0.0)) + min(max(q.x,max(q.y,q.z)),0.0);
 }
 
+// what the orbit did at a point, for coloring. Mirrors `Orbit` in sdf.rs
+struct Orbit {
+    // closest the orbit came to each channel's traps
+    vec4 trap;
+    // iteration it escaped at, or u_Iters if it never did
+    int iterations;
+};
+
+// distance from an orbit point to every trap; channels no trap feeds stay far away
+vec4 orbitTrap(vec3 z) {
+    vec4 trap = vec4(1000000.0);
+    for (int i = 0; i < u_TrapCount; i++) {
+        vec4 a = u_TrapA[i];
+        vec4 b = u_TrapB[i];
+        float d;
+        switch (u_TrapShape[i]) {
+            case TRAP_POINT: d = length(z - a.xyz); break;
+            case TRAP_PLANE: d = abs(dot(z, a.xyz) - a.w); break;
+            case TRAP_AXIS: d = length(cross(z - a.xyz, b.xyz)); break;
+            case TRAP_CROSS: {
+                vec3 q = abs(z - a.xyz);
+                d = min(length(q.yz), min(length(q.xz), length(q.xy)));
+                break;
+            }
+            case TRAP_BOX: d = abs(sdBox(z - a.xyz, b.xyz)); break;
+            default: d = sdSphere(z - a.xyz, a.w); break;
+        }
+        int c = u_TrapChannel[i];
+        trap[c] = min(trap[c], d);
+    }
+    return trap;
+}
+
+// torus in the xz plane
+float sdTorus( vec3 p, float major_radius, float minor_radius )
+{
+  vec2 q = vec2(length(p.xz) - major_radius, p.y);
+  return length(q) - minor_radius;
+}
+
+float sdCapsule( vec3 p, vec3 a, vec3 b, float r )
+{
+  vec3 pa = p - a, ba = b - a;
+  float h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+  return length(pa - ba * h) - r;
+}
+
+// plane through n * h, n must be normalized
+float sdPlane( vec3 p, vec3 n, float h )
+{
+  return dot(p, n) - h;
+}
+
+// regular tetrahedron with corners at (1,1,1), (-1,-1,1), (1,-1,-1) and (-1,1,-1)
+float sdTetrahedron( vec3 p )
+{
+  float d = max(max(-p.x - p.y - p.z, p.x + p.y - p.z), max(-p.x + p.y + p.z, p.x - p.y + p.z));
+  return (d - 1.0) / sqrt(3.0);
+}
+
 float smin( float a, float b, float k )
 {
     k *= 1.0;
@@ -56,22 +169,21 @@ vec3 cart2polar(vec3 cart) {
 }
 
 
-float mandelbulb(vec3 pos, out float orbit_trap_dist) {
+float mandelbulb(vec3 pos, out Orbit orbit) {
     float power = u_Exp;
-    float sphere_rad = 0.5;
-    // float sphere_rad = u_Exp;
-    // float power = 8.0;
     vec3 z = pos;
     float dr = 1.0;
     float r = 0.0;
 
-    orbit_trap_dist = 1000000.0;
-
     int num_iters = int(u_Iters);
+    orbit = Orbit(vec4(1000000.0), num_iters);
 
     for (int i = 0; i < num_iters; i++) {
         r = length(z);
-        if (r > 2.0) break;
+        if (r > 2.0) {
+            orbit.iterations = i;
+            break;
+        }
 
         // Convert to polar coordinates
         float theta = acos(z.z / r);
@@ -87,21 +199,237 @@ float mandelbulb(vec3 pos, out float orbit_trap_dist) {
         z = zr * vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
         z += pos;
 
-        float dist = sdSphere(z, sphere_rad);
-        orbit_trap_dist = min(orbit_trap_dist, dist);
+        orbit.trap = min(orbit.trap, orbitTrap(z));
     }
 
     return 0.5 * log(r) * r / dr;
 }
 
 
-vec3 gradient(float t) {
-    t = clamp(t, 0.0, 1.0);
+// u_FractalParams[0] = (scale, min radius, fixed radius, folding limit)
+float mandelbox(vec3 pos, out Orbit orbit) {
+    float scale = u_FractalParams[0].x;
+    float min_r2 = u_FractalParams[0].y * u_FractalParams[0].y;
+    float fixed_r2 = u_FractalParams[0].z * u_FractalParams[0].z;
+    // clamp() is undefined for min > max; negative limits fold like zero, as in sdf.rs
+    float limit = max(u_FractalParams[0].w, 0.0);
+
+    vec3 z = pos;
+    float dr = 1.0;
+
+    int num_iters = int(u_Iters);
+    orbit = Orbit(vec4(1000000.0), num_iters);
+
+    for (int i = 0; i < num_iters; i++) {
+        // box fold
+        z = clamp(z, -limit, limit) * 2.0 - z;
+
+        // sphere fold
+        float r2 = dot(z, z);
+        if (r2 < min_r2) {
+            float t = fixed_r2 / min_r2;
+            z *= t;
+            dr *= t;
+        } else if (r2 < fixed_r2) {
+            float t = fixed_r2 / r2;
+            z *= t;
+            dr *= t;
+        }
+
+        z = scale * z + pos;
+        dr = dr * abs(scale) + 1.0;
+
+        orbit.trap = min(orbit.trap, orbitTrap(z));
+    }
+
+    return length(z) / abs(dr);
+}
+
+
+// u_FractalParams[0] = (scale, offset.xyz)
+float menger(vec3 pos, out Orbit orbit) {
+    float scale = u_FractalParams[0].x;
+    vec3 offset = u_FractalParams[0].yzw;
+
+    vec3 z = pos;
+
+    int num_iters = int(u_Iters);
+    orbit = Orbit(vec4(1000000.0), num_iters);
+
+    for (int i = 0; i < num_iters; i++) {
+        z = abs(z);
+        if (z.x < z.y) z.xy = z.yx;
+        if (z.x < z.z) z.xz = z.zx;
+        if (z.y < z.z) z.yz = z.zy;
+
+        z = scale * z - offset * (scale - 1.0);
+        if (z.z < -0.5 * offset.z * (scale - 1.0)) {
+            z.z += offset.z * (scale - 1.0);
+        }
+
+        orbit.trap = min(orbit.trap, orbitTrap(z));
+    }
+
+    return sdBox(z, vec3(1.0)) * pow(scale, -float(num_iters));
+}
+
+
+// u_FractalParams[0] = (scale, offset, -, -)
+float sierpinski(vec3 pos, out Orbit orbit) {
+    float scale = u_FractalParams[0].x;
+    vec3 offset = vec3(u_FractalParams[0].y);
+
+    vec3 z = pos;
+
+    int num_iters = int(u_Iters);
+    orbit = Orbit(vec4(1000000.0), num_iters);
+
+    for (int i = 0; i < num_iters; i++) {
+        // fold across the tetrahedron's symmetry planes
+        if (z.x + z.y < 0.0) z.xy = -z.yx;
+        if (z.x + z.z < 0.0) z.xz = -z.zx;
+        if (z.y + z.z < 0.0) z.zy = -z.yz;
+
+        z = z * scale - offset * (scale - 1.0);
+
+        orbit.trap = min(orbit.trap, orbitTrap(z));
+    }
+
+    return sdTetrahedron(z) * pow(scale, -float(num_iters));
+}
+
+
+// u_FractalParams[0] = c, u_FractalParams[1].x = w of the slice
+float quaternionJulia(vec3 pos, out Orbit orbit) {
+    vec4 c = u_FractalParams[0];
+    vec4 z = vec4(pos, u_FractalParams[1].x);
+    float md2 = 1.0;
+    float mz2 = dot(z, z);
+
+    int num_iters = int(u_Iters);
+    orbit = Orbit(vec4(1000000.0), num_iters);
+
+    for (int i = 0; i < num_iters; i++) {
+        md2 *= 4.0 * mz2;
+        // z = z^2 + c
+        z = vec4(z.x * z.x - dot(z.yzw, z.yzw), 2.0 * z.x * z.yzw) + c;
+        mz2 = dot(z, z);
+
+        orbit.trap = min(orbit.trap, orbitTrap(z.xyz));
+        if (mz2 > 4.0) {
+            orbit.iterations = i + 1;
+            break;
+        }
+    }
+
+    return 0.25 * sqrt(mz2 / md2) * log(mz2);
+}
+
+
+// u_FractalParams[0] = (box size.xyz, sphere size), u_FractalParams[1] = (thickness, bounding radius, -, -)
+float kleinian(vec3 pos, out Orbit orbit) {
+    // clamp() is undefined for min > max; negative sizes fold like zero, as in sdf.rs
+    vec3 box_size = max(u_FractalParams[0].xyz, 0.0);
+    float sphere_size = u_FractalParams[0].w;
+    float thickness = u_FractalParams[1].x;
+
+    vec3 p = pos;
+    float de_factor = 1.0;
+
+    int num_iters = int(u_Iters);
+    orbit = Orbit(vec4(1000000.0), num_iters);
+
+    for (int i = 0; i < num_iters; i++) {
+        p = 2.0 * clamp(p, -box_size, box_size) - p;
+        float k = max(sphere_size / dot(p, p), 1.0);
+        p *= k;
+        de_factor *= k;
+
+        orbit.trap = min(orbit.trap, orbitTrap(p));
+    }
+
+    float rxy = length(p.xy);
+    float d = max(rxy - thickness, abs(rxy * p.z) / length(p)) / de_factor;
+    return max(d, sdSphere(pos, u_FractalParams[1].y));
+}
+
 
-    vec3 darkBlue = vec3(0.0, 0.0, 0.1);  // Extremely dark blue
-    vec3 softPink = vec3(0.8, 0.5, 0.6);  // Not super light pink
+// u_FractalParams[0] = (scale, bounding radius, -, -)
+float apollonian(vec3 pos, out Orbit orbit) {
+    float s = u_FractalParams[0].x;
 
-    return mix(darkBlue, softPink, t);
+    vec3 p = pos;
+    float scale = 1.0;
+
+    int num_iters = int(u_Iters);
+    orbit = Orbit(vec4(1000000.0), num_iters);
+
+    for (int i = 0; i < num_iters; i++) {
+        p = -1.0 + 2.0 * fract(0.5 * p + 0.5);
+        float r2 = dot(p, p);
+        orbit.trap = min(orbit.trap, orbitTrap(p));
+
+        float k = s / r2;
+        p *= k;
+        scale *= k;
+    }
+
+    float d = 0.25 * abs(p.y) / scale;
+    return max(d, sdSphere(pos, u_FractalParams[0].y));
+}
+
+
+float fractal(vec3 pos, out Orbit orbit) {
+    switch (u_Fractal) {
+        case FRACTAL_MANDELBOX: return mandelbox(pos, orbit);
+        case FRACTAL_MENGER: return menger(pos, orbit);
+        case FRACTAL_SIERPINSKI: return sierpinski(pos, orbit);
+        case FRACTAL_JULIA: return quaternionJulia(pos, orbit);
+        case FRACTAL_KLEINIAN: return kleinian(pos, orbit);
+        case FRACTAL_APOLLONIAN: return apollonian(pos, orbit);
+        default: return mandelbulb(pos, orbit);
+    }
+}
+
+
+// The scene's map() is generated from the SDF graph (sdf_graph.rs) and inserted here.
+// It has the signature `float map(vec3 p, out Orbit orbit)`.
+// @map
+
+
+vec3 sampleGradient(float t) {
+    if (u_StopCount == 0) {
+        return u_Cosine[0] + u_Cosine[1] * cos(6.28318530718 * (u_Cosine[2] * t + u_Cosine[3]));
+    }
+
+    t = clamp(t, 0.0, 1.0);
+    vec3 color = u_GradientStops[0].rgb;
+    for (int i = 1; i < u_StopCount; i++) {
+        vec4 a = u_GradientStops[i - 1];
+        vec4 b = u_GradientStops[i];
+        if (t >= a.w) {
+            color = mix(a.rgb, b.rgb, clamp((t - a.w) / max(b.w - a.w, 1e-5), 0.0, 1.0));
+        }
+    }
+    return color;
+}
+
+// surface color before lighting
+vec3 albedo(vec3 normal, Orbit orbit, vec3 hitPos) {
+    vec4 trap = orbit.trap;
+    float value;
+    switch (u_ColorMode) {
+        case COLOR_ORBIT_TRAP: value = trap.w; break;
+        case COLOR_ITERATIONS: value = float(orbit.iterations) / float(max(u_Iters, 1u)); break;
+        case COLOR_DISTANCE: value = length(hitPos); break;
+        case COLOR_TRAP_CHANNELS: return 1.0 - clamp(trap.rgb * u_ColorScale + u_ColorOffset, 0.0, 1.0);
+        default: {
+            vec3 n = (normal + 0.8) / 2;
+            float brightness = clamp(trap.w, 0, 1);
+            return brightness * ((u_Palette[0] * n.x) + (u_Palette[1] * n.y) + (u_Palette[2] * n.z));
+        }
+    }
+    return sampleGradient(value * u_ColorScale + u_ColorOffset);
 }
 
 
@@ -109,25 +437,111 @@ vec3 gradient(float t) {
 
 vec3 getNormal(vec3 p) {
     //d is distance of the active ray
-    float tmp;
-    float d = mandelbulb(p, tmp);
+    Orbit tmp;
+    float d = map(p, tmp);
     vec2 e = vec2(0.001, 0);
     vec3 n = d - vec3(
-        mandelbulb(p - e.xyy, tmp),
-        mandelbulb(p - e.yxy, tmp),
-        mandelbulb(p - e.yyx, tmp)
+        map(p - e.xyy, tmp),
+        map(p - e.yxy, tmp),
+        map(p - e.yyx, tmp)
     );
 
     return normalize(n);
 }
 
 
+#define SHADOW_DISTANCE 20.0
+#define SHADOW_STEPS 64
+#define SHADOW_BIAS 0.002
+
+// penumbra from the closest miss along the shadow ray, 0 is fully shadowed
+float softShadow(vec3 ro, vec3 rd, float maxT, float hardness) {
+    Orbit tmp;
+    float res = 1.0;
+    float t = 0.01;
+    for (int i = 0; i < SHADOW_STEPS && t < maxT; i++) {
+        float h = map(ro + rd * t, tmp);
+        if (h < 0.0005) {
+            return 0.0;
+        }
+        res = min(res, hardness * h / t);
+        t += clamp(h, 0.005, 0.25);
+    }
+    return clamp(res, 0.0, 1.0);
+}
+
+// 1 is unoccluded
+float ambientOcclusion(vec3 p, vec3 n) {
+    Orbit tmp;
+    float occ = 0.0;
+    float scale = 1.0;
+    for (int i = 0; i < 5; i++) {
+        float h = 0.01 + 0.12 * float(i) / 4.0;
+        float d = map(p + n * h, tmp);
+        occ += (h - d) * scale;
+        scale *= 0.95;
+    }
+    return clamp(1.0 - 3.0 * occ, 0.0, 1.0);
+}
+
+vec3 shade(vec3 albedo, vec3 p, vec3 n, vec3 rd) {
+    if (!u_Lighting) {
+        return albedo;
+    }
+
+    float ao = u_AO ? 1.0 + (ambientOcclusion(p, n) - 1.0) * u_AOStrength : 1.0;
+    vec3 light = vec3(u_Ambient * ao);
+    vec3 spec = vec3(0.0);
+
+    for (int i = 0; i < u_LightCount; i++) {
+        vec4 v = u_LightVector[i];
+        vec3 l;
+        float maxT;
+        float attenuation;
+        if (v.w == 0.0) {
+            l = normalize(v.xyz);
+            maxT = SHADOW_DISTANCE;
+            attenuation = 1.0;
+        } else {
+            vec3 toLight = v.xyz - p;
+            float d = length(toLight);
+            l = toLight / d;
+            maxT = d;
+            attenuation = 1.0 / (1.0 + d * d);
+        }
+
+        float diffuse = max(dot(n, l), 0.0);
+        if (diffuse <= 0.0) {
+            continue;
+        }
+        float shadow = u_Shadows ? softShadow(p + n * SHADOW_BIAS, l, maxT, u_ShadowHardness) : 1.0;
+        vec3 c = u_LightColor[i] * (attenuation * shadow);
+        light += c * diffuse;
+        if (u_Specular) {
+            vec3 h = normalize(l - rd);
+            spec += c * pow(max(dot(n, h), 0.0), u_Shininess);
+        }
+    }
+
+    return albedo * light + spec;
+}
+
+
+// Window-space depth of a world position, the same value rasterizing a triangle there
+// would give, so models and overlays occlude and are occluded by the fractal.
+float fragDepth(vec3 pos) {
+    vec4 clip = u_ViewProj * vec4(pos, 1.0);
+    float ndc_z = clip.z / clip.w;
+    return clamp(0.5 * (gl_DepthRange.diff * ndc_z + gl_DepthRange.near + gl_DepthRange.far), gl_DepthRange.near, gl_DepthRange.far);
+}
+
+
 
 
 void main() {
     //Ray Marching Time
     Ray ray;
-    vec4 ndc = vec4(fs_uv, -1.0, 1.0);
+    vec4 ndc = vec4(fs_uv + u_Jitter, -1.0, 1.0);
 
     vec4 near = u_InvViewProj * ndc;
     near /= near.w;
@@ -145,7 +559,7 @@ void main() {
 
     bool hit = false;
     float dist = 0;
-    float orbit_trap;
+    Orbit orbit;
  
 
     const vec3 sphereCol = vec3(1, 0.2, 0.7);
@@ -156,13 +570,13 @@ void main() {
 
     vec3 hitPos;
 
-    while(t < 500.0) {
-        // float sphereHit = sdSphere(getRayPos(ray, t) - u_SpherePos, 20);
-        // float boxHit = sdBox(getRayPos(ray, t), vec3(25));
-        // float hitDist = smin(sphereHit, boxHit, 2.0);
-        float hitDist = mandelbulb(getRayPos(ray, t), orbit_trap);
+    int steps = 0;
+
+    // FAR_PLANE in camera.rs
+    while(t < 500.0 && steps < MAX_STEPS) {
+        float hitDist = map(getRayPos(ray, t), orbit);
         
-        float hit_delta = float(1) / float(10000 * u_Detail);
+        float hit_delta = 1.0 / (10000.0 * float(u_Detail));
 
         if (hitDist < hit_delta) {
             hit = true;
@@ -172,36 +586,17 @@ void main() {
             break;
         }
         t += hitDist;
+        steps++;
     }
 
     if(!hit) {  
         frag_color = vec4(0.1, 0.15, 0.25, 1.0);
+        gl_FragDepth = gl_DepthRange.far;
     } else {
-        //max(0.3, 1-(t/10.0))*
-        // float lightVal = dot(getNormal(hitPos), normalize(vec3(0, -1, -1)));
-        // frag_color = (max(0.5+lightVal/2, 0.6))*(fiveColorGradient(value / 1.0));
-        // frag_color = vec4(getNormal(hitPos), 0.0);
         vec3 normal = getNormal(hitPos);
-        normal = (normal + 0.8) / 2;
-        // normal = abs(normal);
-
-        // vec3 first = vec3(0.66, 0.87, 0.886) * 2;
-        // vec3 second = vec3(0.815, 0.639, 0.804) * 2;
-        // vec3 third = vec3(0.96, 0.85, 0.882) * 2;
-
-        float lighting = dot(normalize(normal), vec3(0, 1, 0));
-        lighting = (lighting + 1) / 2;
-
-        // lighting = lighting * 0.6  + 0.4;
-        lighting = orbit_trap;
-        lighting = clamp(lighting, 0, 1);
-        // lighting = 1.0;
-
-        // frag_color = vec4(normal, 1.0);
-        // frag_color = vec4(lighting * ((vec3(1.0, .4, 0.6) * normal.x) + (vec3(.3, .1, 0.8) * normal.y) + (vec3(.9, .6, .6) * normal.z)), 1.0);
-        frag_color = vec4(lighting * ((vec3(1.0, .4, 0.6) * normal.x) + (vec3(.3, .1, 0.8) * normal.y) + (vec3(.9, .6, .6) * normal.z)), 1.0);
-        // frag_color = vec4(lighting * vec3(1.0, .71, 0.8), 1.0);
-        // frag_color = vec4(vec3(lighting), 1.0);
-        // frag_color = vec4(vec3(lighting), 1.0);
+        color = albedo(normal, orbit, hitPos);
+        frag_color = vec4(shade(color, hitPos, normal, ray.direction), 1.0);
+
+        gl_FragDepth = fragDepth(hitPos);
     }
 }
\ No newline at end of file
@@ -1,39 +1,136 @@
 #![windows_subsystem = "windows"]
 
-use std::{collections::btree_map::Range, iter, ops::RangeInclusive, sync::{Arc, Mutex}, time::Instant};
+use std::{ops::RangeInclusive, path::PathBuf, sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::Instant};
+
+use export::ExportJob;
+use accumulation::{Accumulator, SceneKey};
+use profiler::{FrameSample, GpuTimer, Profiler};
+use resolution::ResolutionScaler;
+use model::{Model, ModelLayer};
+use debug_overlay::{DebugLayer, DebugOverlay};
+use camera_controls::{CameraControls, CameraMode};
+use framebuffer::{ColorFormat, Framebuffer};
 
 use mesh::Mesh;
 
-use camera::Camera;
+use meshview::{animation::{Interpolation, Timeline}, bookmark::{Bookmark, FlyTo}, camera::Camera, lighting::Lighting, mesh_export::{self, MeshError, MeshSettings}, orbit_trap::OrbitTraps, palette::Coloring, render::{self, DEFAULT_PALETTE}, scene::Scene, sdf::{FractalParams, FractalType}, sdf_graph::{self, SdfNode}, shader_params::{ParamValue, ShaderParams}};
 use eframe::{egui, egui_glow, glow::HasContext};
 use egui::{DragValue, Margin};
-use nalgebra::{Vector2, Vector3};
+use nalgebra::{Vector2, Vector3, Vector4};
+
+mod error;
+use error::GlError;
 
 mod shader;
 use shader::ShaderProgram;
 
+mod uniforms;
+use uniforms::UniformRegistry;
+
+mod shader_ui;
+use shader_ui::UiControl;
+mod widgets;
+
 mod mesh;
+mod model;
+mod debug_overlay;
+mod camera_controls;
+
+mod cli;
+
+mod export;
 
+mod framebuffer;
+mod accumulation;
+mod resolution;
+mod profiler;
 
-mod camera;
+mod fractal_ui;
+
+mod graph_ui;
+
+mod lighting_ui;
+mod trap_ui;
+
+mod palette_ui;
+use graph_ui::GraphEditor;
+
+mod timeline_ui;
+
+mod shader_reload;
+use shader_reload::{ShaderFailure, ShaderSource, ShaderWatcher};
+use clap::Parser;
 
 
 fn main() -> eframe::Result{
+    let args = cli::Args::parse();
+    if args.output.is_some() || args.mesh.is_some() {
+        if let Err(err) = cli::run(&args) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([420.0, 600.0]).with_position([100.0, 100.0]),
-        multisampling: 4,
         renderer: eframe::Renderer::Glow,
-        depth_buffer: 16,
+        // 24 bits keeps model and fractal depth apart over the long near..far range
+        depth_buffer: 24,
         ..Default::default()
     };
     eframe::run_native(
         "Raymarcher",
         options,
-        Box::new(|cc| Ok(Box::new(App::new(cc)))),
+        Box::new(|cc| Ok(match App::new(cc, ShaderSource::new(args.shader_dir)) {
+            Ok(app) => Box::new(app),
+            Err(err) => Box::new(ErrorApp { message: err.to_string() }),
+        })),
     )
 }
 
 
+/// Builds the program along with the controls its `// @ui` annotations ask for.
+fn build_shader(gl: &eframe::glow::Context, source: &ShaderSource, graph: &SdfNode) -> Result<(ShaderProgram, Vec<Result<UiControl, String>>), ShaderFailure> {
+    let (vert, on_disk) = source.load()?;
+    let frag = sdf_graph::inject_map(&on_disk, graph).map_err(GlError::Graph)?;
+
+    let program = ShaderProgram::new(gl, &vert, &frag).map_err(|err| ShaderFailure::new(err, &vert, &on_disk, &frag))?;
+    Ok((program, shader_ui::parse_controls(&frag)))
+}
+
+
+/// Annotated uniforms that are also `App` fields, because the timeline, scene files and
+/// CPU renderer need them by name.
+const BOUND_PARAMS: [&str; 4] = ["u_Exp", "u_Iters", "u_Detail", "u_Fractal"];
+
+
+/// Stands in for the viewer when GL setup failed, so the user gets the reason instead of a crash.
+struct ErrorApp {
+    message: String,
+}
+
+impl eframe::App for ErrorApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Could not start the renderer");
+            ui.add_space(8.0);
+            ui.label(egui::RichText::new(&self.message).monospace());
+        });
+    }
+}
+
+
+/// Points GL back at egui's framebuffer and the paint callback's viewport after drawing
+/// offscreen.
+unsafe fn bind_egui_target(gl: &eframe::glow::Context, info: &egui::PaintCallbackInfo, painter: &egui_glow::Painter) {
+    gl.bind_framebuffer(eframe::glow::FRAMEBUFFER, painter.intermediate_fbo());
+    let vp = info.viewport_in_pixels();
+    gl.viewport(vp.left_px, vp.from_bottom_px, vp.width_px, vp.height_px);
+    gl.enable(eframe::glow::SCISSOR_TEST);
+}
+
+
 // Main App UI
 
 struct App {
@@ -41,18 +138,114 @@ struct App {
     camera: Arc<Mutex<Camera>>,
     shader_program: Arc<Mutex<ShaderProgram>>,
     value: f32,
-    angle: (f32, f32, f32),
+    camera_controls: CameraControls,
     speed: f32,
     sphere_pos: Vector3<f32>,
-    start_time: Instant,
     animating: bool,
+    timeline: Timeline,
+    time: f32,
+    key_interpolation: Interpolation,
+    export: Option<ExportJob>,
+    export_fps: f32,
+    export_size: (u32, u32),
+    export_error: Arc<Mutex<Option<String>>>,
+    /// Frame number the paint callback last wrote to disk
+    export_written: Arc<Mutex<Option<u32>>>,
+    mesh_settings: MeshSettings,
+    /// Mesh extraction running in the background. Yields the path and triangle count
+    mesh_export: Option<JoinHandle<Result<(PathBuf, usize), MeshError>>>,
+    framebuffer: Arc<Mutex<Option<Framebuffer>>>,
+    accumulator: Arc<Mutex<Option<Accumulator>>>,
+    models: Arc<Mutex<ModelLayer>>,
+    model_error: Arc<Mutex<Option<String>>>,
+    debug: DebugOverlay,
+    debug_layer: Arc<Mutex<Option<DebugLayer>>>,
+    debug_error: Arc<Mutex<Option<String>>>,
+    accumulation_error: Arc<Mutex<Option<String>>>,
+    /// Average jittered samples over frames while the view is still
+    progressive: bool,
+    max_samples: u32,
+    resolution: ResolutionScaler,
+    profiler: Profiler,
+    gpu_timer: Arc<Mutex<GpuTimer>>,
+    /// Canvas size in physical pixels
+    viewport_px: (i32, i32),
     exp: f32,
     num_iters: u32,
     detail: i32,
+    fractal: FractalType,
+    fractal_params: FractalParams,
+    graph: SdfNode,
+    compiled_graph: SdfNode,
+    shader_source: ShaderSource,
+    controls: Vec<Result<UiControl, String>>,
+    shader_params: ShaderParams,
+    shader_watcher: Option<ShaderWatcher>,
+    shader_error: Option<ShaderFailure>,
+    graph_editor: GraphEditor,
+    palette: [Vector3<f32>; 3],
+    coloring: Coloring,
+    orbit_traps: OrbitTraps,
+    lighting: Lighting,
+    status: Option<String>,
+    bookmarks: Vec<Bookmark>,
+    bookmark_name: String,
+    fly_to: Option<FlyTo>,
 }
 
 impl eframe::App for App {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let update_start = Instant::now();
+
+        if let Some(err) = self.export_error.lock().unwrap().take() {
+            self.export = None;
+            self.status = Some(format!("Export failed: {err}"));
+        }
+        if let Some(written) = self.export_written.lock().unwrap().take() {
+            if let Some(job) = &mut self.export {
+                job.advance(written);
+                if job.is_done() {
+                    self.status = Some(format!("Exported {} frames to {}", job.frames.len(), job.dir.display()));
+                    self.export = None;
+                }
+            }
+        }
+        if self.mesh_export.as_ref().is_some_and(|job| job.is_finished()) {
+            self.status = Some(match self.mesh_export.take().unwrap().join() {
+                Ok(Ok((path, triangles))) => format!("Wrote {} ({triangles} triangles)", path.display()),
+                Ok(Err(err)) => format!("Mesh export failed: {err}"),
+                Err(_) => "Mesh export panicked".to_owned(),
+            });
+        }
+        if let Some(err) = self.model_error.lock().unwrap().take() {
+            // hidden so the same error doesn't come back every frame
+            self.models.lock().unwrap().models.iter_mut().for_each(|model| model.visible = false);
+            self.status = Some(format!("Could not draw models: {err}"));
+        }
+        if let Some(err) = self.debug_error.lock().unwrap().take() {
+            self.debug.enabled = false;
+            self.status = Some(format!("Debug overlay turned off: {err}"));
+        }
+        if let Some(err) = self.accumulation_error.lock().unwrap().take() {
+            self.progressive = false;
+            self.resolution.enabled = false;
+            self.status = Some(format!("Offscreen rendering turned off: {err}"));
+        }
+        let moving = self.accumulator.lock().unwrap().as_ref().is_some_and(|accumulator| accumulator.moving());
+        self.resolution.update(ctx.input(|i| i.stable_dt), moving);
+
+        if let Some(job) = &self.export {
+            self.time = self.timeline.wrap_time(job.time());
+            self.apply_timeline();
+        } else if self.animating {
+            let time = self.time + ctx.input(|i| i.stable_dt);
+            if !self.timeline.looping && time >= self.timeline.duration {
+                self.animating = false;
+            }
+            self.time = self.timeline.wrap_time(time);
+            self.apply_timeline();
+        }
+
         egui::TopBottomPanel::top("Top Panel")
             .frame(egui::Frame { inner_margin: 
                 Margin { 
@@ -60,7 +253,20 @@ impl eframe::App for App {
                 }, 
                 ..egui::Frame::default()
             })
-            .show(ctx, |_| ());
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Open").clicked() {
+                        self.open_scene();
+                    }
+                    if ui.button("Save").clicked() {
+                        self.save_scene();
+                    }
+                    ui.toggle_value(&mut self.profiler.visible, "Profiler");
+                    if let Some(status) = &self.status {
+                        ui.label(status);
+                    }
+                });
+            });
 
         
 
@@ -81,51 +287,300 @@ impl eframe::App for App {
                     });
                     ui.label("Rotation");
                     ui.horizontal(|ui| {
-                        ui.add(egui::DragValue::new(&mut self.angle.0));
-                        ui.add(egui::DragValue::new(&mut self.angle.1));
-                        ui.add(egui::DragValue::new(&mut self.angle.2));
+                        let mut cam = self.camera.lock().unwrap();
+                        let mut angle = cam.euler_angles();
+                        let changed = ui.add(egui::DragValue::new(&mut angle.0)).changed()
+                            | ui.add(egui::DragValue::new(&mut angle.1)).changed()
+                            | ui.add(egui::DragValue::new(&mut angle.2)).changed();
+                        if changed {
+                            cam.set_euler_angles(angle);
+                        }
+                    });
+                    ui.label("Mode");
+                    ui.horizontal(|ui| {
+                        let mut mode = self.camera_controls.mode;
+                        egui::ComboBox::from_id_salt("camera_mode")
+                            .selected_text(mode.name())
+                            .show_ui(ui, |ui| {
+                                for option in CameraMode::ALL {
+                                    ui.selectable_value(&mut mode, option, option.name());
+                                }
+                            });
+                        if mode != self.camera_controls.mode {
+                            self.camera_controls.set_mode(mode, &mut self.camera.lock().unwrap());
+                        }
                     });
+                    if self.camera_controls.mode != CameraMode::Fly {
+                        widgets::vec3(ui, "Target", &mut self.camera_controls.target);
+                    }
                     ui.label("Speed");
                     ui.horizontal(|ui| {
                         ui.add(egui::Slider::new(&mut self.speed, RangeInclusive::new(0.0, 20.0)));
                     });
 
+                    ui.label("Bookmarks");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.bookmark_name);
+                        if ui.button("Add").clicked() {
+                            let name = if self.bookmark_name.is_empty() {
+                                format!("View {}", self.bookmarks.len() + 1)
+                            } else {
+                                std::mem::take(&mut self.bookmark_name)
+                            };
+                            self.bookmarks.push(Bookmark::from_camera(name, &self.camera.lock().unwrap()));
+                        }
+                    });
+
+                    let mut removed = None;
+                    for (i, bookmark) in self.bookmarks.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui.button("Go").clicked() {
+                                self.fly_to = Some(FlyTo::new(&self.camera.lock().unwrap(), bookmark, 1.5));
+                            }
+                            if ui.button("x").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.label(&bookmark.name);
+                        });
+                    }
+                    if let Some(i) = removed {
+                        self.bookmarks.remove(i);
+                    }
+
                 });
-                ui.checkbox(&mut self.animating, "Animate");
-                // if !self.animating {
-                ui.horizontal(|ui| {
-                    ui.label("Exp");
-                    ui.add_enabled(!self.animating, egui::Slider::new(&mut self.exp, RangeInclusive::new(0.0, 30.0)));
+                ui.collapsing("Timeline", |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button(if self.animating { "Pause" } else { "Play" }).clicked() {
+                            self.animating = !self.animating;
+                        }
+                        ui.label(format!("{:.2} / {:.2} s", self.time, self.timeline.duration));
+                        ui.checkbox(&mut self.timeline.looping, "Loop");
+                        ui.label("Length");
+                        ui.add(DragValue::new(&mut self.timeline.duration).range(RangeInclusive::new(0.1, 3600.0)).suffix(" s"));
+                    });
+
+                    if timeline_ui::scrub_bar(ui, &self.timeline, &mut self.time) {
+                        self.apply_timeline();
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("New keys");
+                        egui::ComboBox::from_id_salt("key_interpolation")
+                            .selected_text(format!("{:?}", self.key_interpolation))
+                            .show_ui(ui, |ui| {
+                                for interpolation in Interpolation::ALL {
+                                    ui.selectable_value(&mut self.key_interpolation, interpolation, format!("{interpolation:?}"));
+                                }
+                            });
+                    });
+
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.button("Key camera").clicked() {
+                            self.key_camera();
+                        }
+                        if ui.button("Key exp").clicked() {
+                            self.timeline.exp.insert(self.time, self.exp, self.key_interpolation);
+                        }
+                        if ui.button("Key iterations").clicked() {
+                            self.timeline.iterations.insert(self.time, self.num_iters as f32, self.key_interpolation);
+                        }
+                        if ui.button("Key detail").clicked() {
+                            self.timeline.detail.insert(self.time, self.detail as f32, self.key_interpolation);
+                        }
+                        if ui.button("Key palette").clicked() {
+                            for (track, color) in self.timeline.palette.iter_mut().zip(self.palette) {
+                                track.insert(self.time, color, self.key_interpolation);
+                            }
+                        }
+                    });
+
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("Clear");
+                        if ui.button("camera").clicked() {
+                            self.timeline.position.clear();
+                            self.timeline.orientation.clear();
+                        }
+                        if ui.button("exp").clicked() {
+                            self.timeline.exp.clear();
+                        }
+                        if ui.button("iterations").clicked() {
+                            self.timeline.iterations.clear();
+                        }
+                        if ui.button("detail").clicked() {
+                            self.timeline.detail.clear();
+                        }
+                        if ui.button("palette").clicked() {
+                            self.timeline.palette.iter_mut().for_each(|track| track.clear());
+                        }
+                    });
                 });
 
-                ui.horizontal(|ui| {
-                    ui.label("Iterations");
+                ui.collapsing("Export", |ui| {
+                    let frame_count = (self.timeline.duration * self.export_fps).ceil().max(1.0) as u32;
+
+                    ui.horizontal(|ui| {
+                        ui.label("Size");
+                        ui.add(DragValue::new(&mut self.export_size.0).range(RangeInclusive::new(1, 8192)));
+                        ui.add(DragValue::new(&mut self.export_size.1).range(RangeInclusive::new(1, 8192)));
+                        ui.label("FPS");
+                        ui.add(DragValue::new(&mut self.export_fps).range(RangeInclusive::new(1.0, 240.0)));
+                    });
 
-                    if ui.button("-").clicked() {
-                        self.num_iters -= 1;
+                    match self.export.as_ref().map(|job| job.progress()) {
+                        Some(progress) => {
+                            ui.horizontal(|ui| {
+                                ui.add(egui::ProgressBar::new(progress).desired_width(200.0).show_percentage());
+                                if ui.button("Cancel").clicked() {
+                                    self.export = None;
+                                    self.status = Some("Export cancelled".to_owned());
+                                }
+                            });
+                        }
+                        None => {
+                            ui.horizontal(|ui| {
+                                if ui.button("Export frames...").clicked() {
+                                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                                        let camera = self.camera.lock().unwrap().clone();
+                                        self.export = Some(ExportJob::new(dir, self.export_fps, self.export_size.0, self.export_size.1, 0..frame_count, camera));
+                                        *self.export_written.lock().unwrap() = None;
+                                        self.fly_to = None;
+                                    }
+                                }
+                                ui.label(format!("{frame_count} frames"));
+                            });
+                        }
                     }
 
-                    ui.add(DragValue::new(&mut self.num_iters).range(RangeInclusive::new(1, 40)));
+                    ui.separator();
+                    widgets::vec3(ui, "Mesh min", &mut self.mesh_settings.min);
+                    widgets::vec3(ui, "Mesh max", &mut self.mesh_settings.max);
+                    ui.horizontal(|ui| {
+                        ui.label("Resolution");
+                        ui.add(DragValue::new(&mut self.mesh_settings.resolution).range(RangeInclusive::new(2, mesh_export::MAX_RESOLUTION)));
+                        if self.mesh_export.is_some() {
+                            ui.spinner();
+                            ui.label("Extracting...");
+                        } else if ui.button("Export mesh...").clicked() {
+                            let dialog = rfd::FileDialog::new()
+                                .add_filter("Wavefront OBJ", &["obj"])
+                                .add_filter("Binary STL", &["stl"])
+                                .add_filter("Binary PLY", &["ply"])
+                                .set_file_name("fractal.obj");
+                            if let Some(path) = dialog.save_file() {
+                                let settings = self.scene().render_settings(0, 0);
+                                let mesh_settings = self.mesh_settings;
+                                self.mesh_export = Some(thread::spawn(move || {
+                                    let mesh = mesh_export::extract(&settings, &mesh_settings)?;
+                                    mesh.save(&path)?;
+                                    Ok((path, mesh.triangles.len()))
+                                }));
+                            }
+                        }
+                    });
+                });
 
-                    if ui.button("+").clicked() {
-                        self.num_iters += 1;
+                ui.collapsing("Models", |ui| {
+                    if ui.button("Import OBJ...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().add_filter("Wavefront OBJ", &["obj"]).pick_file() {
+                            match Model::load(&path) {
+                                Ok(model) => self.models.lock().unwrap().models.push(model),
+                                Err(err) => self.status = Some(format!("Could not import {err}")),
+                            }
+                        }
+                    }
+
+                    let mut layer = self.models.lock().unwrap();
+                    let mut remove = None;
+                    for (i, model) in layer.models.iter_mut().enumerate() {
+                        ui.push_id(i, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut model.visible, &model.name);
+                                ui.label(format!("{} tris", model.triangle_count()));
+                                if ui.small_button("x").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                            widgets::vec3(ui, "Position", &mut model.position);
+                            ui.horizontal(|ui| {
+                                ui.label("Scale");
+                                ui.add(DragValue::new(&mut model.scale).speed(0.01).range(RangeInclusive::new(0.001, 1000.0)));
+                                ui.color_edit_button_rgb(&mut model.color);
+                            });
+                        });
+                    }
+                    if let Some(i) = remove {
+                        layer.remove(i);
                     }
                 });
 
                 ui.horizontal(|ui| {
-                    ui.label("Detail");
+                    ui.label("Fractal");
+                    egui::ComboBox::from_id_salt("fractal_type")
+                        .selected_text(self.fractal.name())
+                        .show_ui(ui, |ui| {
+                            for kind in FractalType::ALL {
+                                ui.selectable_value(&mut self.fractal, kind, kind.name());
+                            }
+                        });
+                });
+
+                self.store_bound_params();
+                let timeline = &self.timeline;
+                let keyed = |name: &str| self.animating && match name {
+                    "u_Exp" => !timeline.exp.is_empty(),
+                    "u_Iters" => !timeline.iterations.is_empty(),
+                    "u_Detail" => !timeline.detail.is_empty(),
+                    _ => false,
+                };
+                shader_ui::controls_ui(ui, &self.controls, &mut self.shader_params, keyed);
+                self.load_bound_params();
+
+                fractal_ui::fractal_params_ui(ui, self.fractal, &mut self.fractal_params);
 
-                    if ui.button("-").clicked() {
-                        self.detail -= 1;
+                ui.collapsing("Scene Graph", |ui| {
+                    self.graph_editor.show(ui, &mut self.graph);
+                });
+
+                ui.collapsing("Coloring", |ui| {
+                    if let Some(status) = palette_ui::coloring_ui(ui, &mut self.coloring, &mut self.palette) {
+                        self.status = Some(status);
                     }
+                    ui.collapsing("Orbit traps", |ui| {
+                        trap_ui::orbit_traps_ui(ui, &mut self.orbit_traps);
+                    });
+                });
+
+                ui.collapsing("Lighting", |ui| {
+                    lighting_ui::lighting_ui(ui, &mut self.lighting);
+                });
 
-                    ui.add(DragValue::new(&mut self.detail).range(RangeInclusive::new(1, 100)));
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.progressive, "Progressive AA");
+                    ui.add_enabled(self.progressive, DragValue::new(&mut self.max_samples).range(RangeInclusive::new(1, 4096)).prefix("max "));
+                    if self.progressive {
+                        let samples = self.accumulator.lock().unwrap().as_ref().map_or(0, |accumulator| accumulator.samples());
+                        ui.label(format!("{samples} samples"));
+                    }
+                });
 
-                    if ui.button("+").clicked() {
-                        self.detail += 1;
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.resolution.enabled, "Dynamic resolution");
+                    ui.add_enabled(self.resolution.enabled, DragValue::new(&mut self.resolution.target_fps).range(RangeInclusive::new(5.0, 144.0)).suffix(" fps"));
+                    if self.resolution.enabled {
+                        ui.label(format!("{:.0}% while moving", self.resolution.scale() * 100.0));
                     }
                 });
 
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.debug.enabled, "Debug overlay");
+                    ui.add_enabled_ui(self.debug.enabled, |ui| {
+                        ui.checkbox(&mut self.debug.axes, "Axes");
+                        ui.checkbox(&mut self.debug.grid, "Grid");
+                        ui.checkbox(&mut self.debug.bailout_sphere, "Bailout");
+                        ui.checkbox(&mut self.debug.camera_vectors, "Camera");
+                    });
+                });
+
                 // }
             });
 
@@ -136,61 +591,58 @@ impl eframe::App for App {
         });
 
 
-        // update logic
-        let rot = nalgebra::Rotation3::from_euler_angles(
-            self.angle.0.to_radians(), 
-            self.angle.1.to_radians(), 
-            self.angle.2.to_radians()
-        );
-
         // MOVEMENT HANDLER 
-        {
-            let speed = 
-                if ctx.input(|i| i.modifiers.shift) {self.speed * 2.0} 
-                else if ctx.input(|i| i.modifiers.ctrl) {self.speed * 0.2}
-                else {self.speed};
+        // exports render from the timeline alone, so the camera stays put until they finish
+        if self.export.is_none() {
+            self.camera_controls.keys(ctx, &mut self.camera.lock().unwrap(), self.speed);
 
-            if ctx.input(|i| i.key_down(egui::Key::W)) {
-                let mut cam = self.camera.lock().unwrap();
-                let look = cam.look;
-                cam.pos += look * 0.01 * speed;
-            }
-            if ctx.input(|i| i.key_down(egui::Key::S)) {
-                let mut cam = self.camera.lock().unwrap();
-                let look = cam.look;
-                cam.pos += look * -0.01 * speed;
-            }
-    
-            if ctx.input(|i| i.key_down(egui::Key::A)) {
+            if let Some(fly_to) = &mut self.fly_to {
+                let dt = ctx.input(|i| i.stable_dt);
                 let mut cam = self.camera.lock().unwrap();
-                let right = cam.right;
-                cam.pos += right * -0.01 * speed;
+                if fly_to.step(dt, &mut cam) {
+                    self.fly_to = None;
+                }
             }
-    
-            if ctx.input(|i| i.key_down(egui::Key::D)) {
-                let mut cam = self.camera.lock().unwrap();
-                let right = cam.right;
-                cam.pos += right * 0.01 * speed;
-            }
-    
-            if ctx.input(|i| i.key_down(egui::Key::Q)) {
-                let mut cam = self.camera.lock().unwrap();
-                let up = cam.get_up_vec() ;
-                cam.pos += up * -0.01 * speed;
+        }
+
+        // recompile once the user lets go, not on every drag step
+        let graph_changed = self.graph != self.compiled_graph && !ctx.input(|i| i.pointer.any_down());
+        let files_changed = self.shader_watcher.as_mut().is_some_and(|watcher| watcher.poll());
+        if files_changed || graph_changed {
+            if let Some(gl) = frame.gl() {
+                self.rebuild_shader(gl);
             }
-            
-            if ctx.input(|i| i.key_down(egui::Key::E)) {
-                let mut cam = self.camera.lock().unwrap();
-                let up = cam.get_up_vec() ;
-                cam.pos += up * 0.01 * speed;
+        }
+
+        let problems = self.shader_program.lock().unwrap().uniforms.take_problems();
+        if !problems.is_empty() {
+            self.status = Some(problems.iter().map(|problem| problem.to_string()).collect::<Vec<_>>().join("; "));
+        }
+
+        if let Some(failure) = &self.shader_error {
+            if !shader_reload::error_window(ctx, failure) {
+                self.shader_error = None;
             }
-    
         }
 
-        let look = rot * Vector3::new(0.0, 0.0, -1.0);
-        let right = rot * Vector3::new(1.0, 0.0, 0.0);
-        self.camera.lock().unwrap().right = right;
-        self.camera.lock().unwrap().look = look;
+        if self.profiler.visible {
+            if let Some(status) = self.profiler.overlay(ctx, &self.shader_params) {
+                self.status = Some(status);
+            }
+        }
+        let resolution = match self.accumulator.lock().unwrap().as_ref() {
+            Some(accumulator) if self.progressive || self.resolution.enabled => accumulator.size(),
+            _ => self.viewport_px,
+        };
+        let sample = FrameSample {
+            frame_ms: ctx.input(|i| i.unstable_dt) * 1000.0,
+            cpu_ms: update_start.elapsed().as_secs_f32() * 1000.0,
+            gpu_ms: self.gpu_timer.lock().unwrap().take_latest(),
+            resolution,
+        };
+        if let Err(err) = self.profiler.record(sample, &self.shader_params) {
+            self.status = Some(format!("Frame log stopped: {err}"));
+        }
         
         ctx.request_repaint();
     }
@@ -198,13 +650,13 @@ impl eframe::App for App {
 
 
 impl App {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let gl = cc
-            .gl
-            .as_ref()
-            .expect("You need to run eframe with the glow backend");
+    fn new(cc: &eframe::CreationContext<'_>, shader_source: ShaderSource) -> Result<Self, GlError> {
+        let gl = cc.gl.as_ref().ok_or(GlError::Resource {
+            object: "OpenGL context",
+            message: "eframe was not started with the glow backend".to_owned(),
+        })?;
 
-        let mesh = Mesh::new(&gl, 
+        let mesh = Mesh::new(gl, 
             [
                 Vector3::new(-1.0, -1.0, 0.0), 
                 Vector3::new(-1.0, 1.0, 0.0),
@@ -214,29 +666,204 @@ impl App {
         [0, 1, 2, 1, 2, 3].to_vec(),
             (0..6).map(|_| Vector2::new(0.0, 0.0)).collect::<Vec<Vector2<f32>>>().to_vec(),
             false
-        );
+        )?;
 
-        let shader_program = ShaderProgram::new(gl, "src/main.vert.glsl", "src/main.frag.glsl");
+        let graph = SdfNode::default();
+        let (shader_program, controls) = build_shader(gl, &shader_source, &graph).map_err(|failure| failure.error)?;
         
         let camera = Camera::default();
+        let gpu_timer = GpuTimer::new(gl)?;
         
-        Self { 
+        Ok(Self { 
             mesh: Arc::new(Mutex::new(mesh)), 
             shader_program: Arc::new(Mutex::new(shader_program)),
             camera: Arc::new(Mutex::new(camera)),
             value: 0.0,
-            angle: (0.0, 0.0, 0.0),
+            camera_controls: CameraControls::default(),
             speed: 1.0,
             sphere_pos: Vector3::new(0.0, 0.0, 0.0),
-            start_time: Instant::now(),
-            animating: true,
+            animating: false,
+            timeline: Timeline::default(),
+            time: 0.0,
+            key_interpolation: Interpolation::default(),
+            export: None,
+            export_fps: 30.0,
+            export_size: (1920, 1080),
+            export_error: Arc::new(Mutex::new(None)),
+            export_written: Arc::new(Mutex::new(None)),
+            mesh_settings: MeshSettings::default(),
+            mesh_export: None,
+            framebuffer: Arc::new(Mutex::new(None)),
+            accumulator: Arc::new(Mutex::new(None)),
+            accumulation_error: Arc::new(Mutex::new(None)),
+            models: Arc::new(Mutex::new(ModelLayer::default())),
+            model_error: Arc::new(Mutex::new(None)),
+            debug: DebugOverlay::default(),
+            debug_layer: Arc::new(Mutex::new(None)),
+            debug_error: Arc::new(Mutex::new(None)),
+            progressive: true,
+            max_samples: 64,
+            resolution: ResolutionScaler::default(),
+            profiler: Profiler::default(),
+            gpu_timer: Arc::new(Mutex::new(gpu_timer)),
+            viewport_px: (0, 0),
             exp: 8.0,
             num_iters: 12,
-            detail: 1
-        }
+            detail: 1,
+            fractal: FractalType::Mandelbulb,
+            fractal_params: FractalParams::default(),
+            compiled_graph: graph.clone(),
+            graph,
+            shader_watcher: shader_source.watcher(),
+            shader_source,
+            controls,
+            shader_params: ShaderParams::new(),
+            shader_error: None,
+            graph_editor: GraphEditor::default(),
+            palette: DEFAULT_PALETTE,
+            coloring: Coloring::default(),
+            orbit_traps: OrbitTraps::default(),
+            lighting: Lighting::default(),
+            status: None,
+            bookmarks: Vec::new(),
+            bookmark_name: String::new(),
+            fly_to: None,
+        })
     }   
 
 
+    /// Swaps in a freshly built program. On failure the old program keeps running and
+    /// the error is shown instead.
+    fn rebuild_shader(&mut self, gl: &eframe::glow::Context) {
+        self.compiled_graph = self.graph.clone();
+        match build_shader(gl, &self.shader_source, &self.graph) {
+            Ok((program, controls)) => {
+                std::mem::replace(&mut *self.shader_program.lock().unwrap(), program).destroy(gl);
+                self.controls = controls;
+                self.shader_error = None;
+            }
+            Err(failure) => self.shader_error = Some(failure),
+        }
+    }
+
+
+    fn scene(&self) -> Scene {
+        let camera = self.camera.lock().unwrap().clone();
+        Scene {
+            position: camera.pos,
+            angle: camera.euler_angles(),
+            orientation: Some(camera.orientation),
+            speed: self.speed,
+            exp: self.exp,
+            num_iters: self.num_iters,
+            detail: self.detail,
+            animating: self.animating,
+            fractal: self.fractal,
+            fractal_params: self.fractal_params,
+            graph: self.graph.clone(),
+            palette: self.palette,
+            coloring: self.coloring.clone(),
+            orbit_traps: self.orbit_traps.clone(),
+            lighting: self.lighting.clone(),
+            bookmarks: self.bookmarks.clone(),
+            timeline: self.timeline.clone(),
+            shader_params: self.shader_params
+                .iter()
+                .filter(|(name, _)| !BOUND_PARAMS.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), *value))
+                .collect(),
+        }
+    }
+
+    fn apply_scene(&mut self, scene: Scene) {
+        let camera = scene.camera();
+        {
+            let mut cam = self.camera.lock().unwrap();
+            cam.pos = camera.pos;
+            cam.orientation = camera.orientation;
+        }
+        self.speed = scene.speed;
+        self.exp = scene.exp;
+        self.num_iters = scene.num_iters;
+        self.detail = scene.detail;
+        self.animating = scene.animating;
+        self.fractal = scene.fractal;
+        self.fractal_params = scene.fractal_params;
+        self.graph = scene.graph;
+        self.palette = scene.palette;
+        self.coloring = scene.coloring;
+        self.orbit_traps = scene.orbit_traps;
+        self.lighting = scene.lighting;
+        self.bookmarks = scene.bookmarks;
+        self.fly_to = None;
+        self.timeline = scene.timeline;
+        self.shader_params = scene.shader_params;
+        self.time = 0.0;
+    }
+
+
+    fn store_bound_params(&mut self) {
+        let values = [
+            ParamValue::Float(self.exp),
+            ParamValue::UInt(self.num_iters),
+            ParamValue::Int(self.detail),
+            ParamValue::Int(self.fractal as i32),
+        ];
+        for (name, value) in BOUND_PARAMS.into_iter().zip(values) {
+            self.shader_params.insert(name.to_owned(), value);
+        }
+    }
+
+    fn load_bound_params(&mut self) {
+        if let Some(ParamValue::Float(exp)) = self.shader_params.get("u_Exp") {
+            self.exp = *exp;
+        }
+        if let Some(ParamValue::UInt(iters)) = self.shader_params.get("u_Iters") {
+            self.num_iters = *iters;
+        }
+        if let Some(ParamValue::Int(detail)) = self.shader_params.get("u_Detail") {
+            self.detail = (*detail).max(1);
+        }
+    }
+
+    fn apply_timeline(&mut self) {
+        let sample = self.timeline.sample(self.time);
+        sample.apply_camera(&mut self.camera.lock().unwrap());
+        sample.apply_params(&mut self.exp, &mut self.num_iters, &mut self.detail, &mut self.palette);
+    }
+
+    fn key_camera(&mut self) {
+        let cam = self.camera.lock().unwrap();
+        self.timeline.position.insert(self.time, cam.pos, self.key_interpolation);
+        self.timeline.orientation.insert(self.time, cam.orientation, self.key_interpolation);
+    }
+
+    fn open_scene(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("Scene", &["json"]).pick_file() else {
+            return;
+        };
+
+        match Scene::load(&path) {
+            Ok(scene) => {
+                self.apply_scene(scene);
+                self.status = Some(format!("Loaded {}", path.display()));
+            }
+            Err(err) => self.status = Some(format!("Could not open {}: {err}", path.display())),
+        }
+    }
+
+    fn save_scene(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("Scene", &["json"]).set_file_name("scene.json").save_file() else {
+            return;
+        };
+
+        self.status = Some(match self.scene().save(&path) {
+            Ok(()) => format!("Saved {}", path.display()),
+            Err(err) => format!("Could not save {}: {err}", path.display()),
+        });
+    }
+
+
     fn custom_painting(&mut self, ui : &mut egui::Ui) {
         let w = ui.available_width();
         let h = ui.available_height();
@@ -245,50 +872,220 @@ impl App {
             ui.allocate_exact_size(egui::vec2(w, h) , egui::Sense::drag());
 
         self.camera.lock().unwrap().aspect_ratio = w/h;
-
-        let _elapsed = self.start_time.elapsed();
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        self.viewport_px = ((w * pixels_per_point).round() as i32, (h * pixels_per_point).round() as i32);
 
 
         let shader_program = self.shader_program.clone();
         let mesh = self.mesh.clone();
         let camera = self.camera.clone();
 
-        self.angle.0 += response.drag_motion().y * -0.1;
-        self.angle.1 += response.drag_motion().x * -0.1;
+        if self.export.is_none() {
+            self.camera_controls.pointer(ui.ctx(), &response, &mut camera.lock().unwrap());
+        }
 
         let _value = self.value;
 
         let _sphere_pos = self.sphere_pos;
-        if self.animating {
-            self.exp = 10.0 * ((self.start_time.elapsed().as_secs_f32() / 8.0).sin() + 1.0);
-        }
 
-        let iters = self.num_iters;
+        self.store_bound_params();
+        let shader_params = self.shader_params.clone();
+        let palette = self.palette;
+        let coloring = self.coloring.clone();
+        let lighting = self.lighting.clone();
+        let lights = lighting.packed();
+        let traps = self.orbit_traps.packed();
+        let packed = self.fractal_params.packed(self.fractal);
+        let fractal_params = [Vector4::from_column_slice(&packed[..4]), Vector4::from_column_slice(&packed[4..])];
+
+        let export_frame = self.export.as_ref().map(|job| (job.frame_path(), job.next, job.width as i32, job.height as i32, job.frame_camera(&self.timeline)));
+        let export_error = self.export_error.clone();
+        let export_written = self.export_written.clone();
+        let framebuffer = self.framebuffer.clone();
+
+        let accumulator = self.accumulator.clone();
+        let accumulation_error = self.accumulation_error.clone();
+        // the offscreen path also serves dynamic resolution, where one sample is enough
+        let offscreen = self.progressive || self.resolution.enabled;
+        let max_samples = if self.progressive { self.max_samples } else { 1 };
+        let motion_scale = self.resolution.scale();
+        let gpu_timer = self.gpu_timer.clone();
+        let profiling = self.profiler.visible || self.profiler.log_path().is_some();
+        // exp, iterations and detail are in the shader params
+        let settings = accumulation::settings_hash(&(
+            (w, h),
+            &self.palette,
+            self.fractal,
+            &self.fractal_params,
+            &self.graph,
+            &self.lighting,
+            &self.coloring,
+            &self.orbit_traps,
+            &self.shader_params,
+        ));
+
+        let set_uniforms = move |gl: &eframe::glow::Context, uniforms: &UniformRegistry| {
+            shader_ui::upload(gl, uniforms, &shader_params);
+            uniforms.set(gl, "u_Jitter", Vector2::<f32>::zeros());
+            uniforms.set(gl, "u_Palette", palette);
 
-        let exp = self.exp;
-        let detail = self.detail;
+            let (stop_count, stops) = coloring.gradient.packed_stops();
+            uniforms.set(gl, "u_ColorMode", coloring.mode as i32);
+            uniforms.set(gl, "u_StopCount", stop_count);
+            uniforms.set(gl, "u_GradientStops", stops);
+            uniforms.set(gl, "u_Cosine", coloring.gradient.packed_cosine());
+            uniforms.set(gl, "u_ColorScale", coloring.scale);
+            uniforms.set(gl, "u_ColorOffset", coloring.offset);
+            uniforms.set(gl, "u_FractalParams", fractal_params);
+
+            uniforms.set(gl, "u_TrapCount", traps.count);
+            uniforms.set(gl, "u_TrapShape", traps.shapes);
+            uniforms.set(gl, "u_TrapChannel", traps.channels);
+            uniforms.set(gl, "u_TrapA", traps.a);
+            uniforms.set(gl, "u_TrapB", traps.b);
+
+            uniforms.set(gl, "u_Lighting", lighting.enabled);
+            uniforms.set(gl, "u_LightCount", lights.count);
+            uniforms.set(gl, "u_LightVector", lights.vectors);
+            uniforms.set(gl, "u_LightColor", lights.colors);
+            uniforms.set(gl, "u_Ambient", lighting.ambient);
+            uniforms.set(gl, "u_Shadows", lighting.shadows);
+            uniforms.set(gl, "u_ShadowHardness", lighting.shadow_hardness);
+            uniforms.set(gl, "u_AO", lighting.ambient_occlusion);
+            uniforms.set(gl, "u_AOStrength", lighting.ao_strength);
+            uniforms.set(gl, "u_Specular", lighting.specular);
+            uniforms.set(gl, "u_Shininess", lighting.shininess);
+        };
+
+        let models = self.models.clone();
+        let model_error = self.model_error.clone();
+        let draw_models = move |gl: &eframe::glow::Context, camera: &Camera| {
+            if let Err(err) = models.lock().unwrap().draw(gl, camera) {
+                *model_error.lock().unwrap() = Some(err.to_string());
+            }
+        };
+
+        let debug = self.debug;
+        let debug_layer = self.debug_layer.clone();
+        let debug_error = self.debug_error.clone();
+        // on screen only, after the models
+        let draw_debug = move |gl: &eframe::glow::Context, info: &egui::PaintCallbackInfo, camera: &Camera| {
+            if !debug.enabled {
+                return;
+            }
+            let mut slot = debug_layer.lock().unwrap();
+            let layer = match slot.as_mut() {
+                Some(layer) => layer,
+                None => match DebugLayer::new(gl) {
+                    Ok(layer) => slot.insert(layer),
+                    Err(err) => {
+                        *debug_error.lock().unwrap() = Some(err.to_string());
+                        return;
+                    }
+                },
+            };
+            let vp = info.viewport_in_pixels();
+            layer.draw(gl, &debug, camera, [vp.left_px, vp.from_bottom_px, vp.width_px, vp.height_px], info.pixels_per_point);
+        };
 
         let callback = egui::PaintCallback {
             rect,
-            callback: std::sync::Arc::new(egui_glow::CallbackFn::new(move |_info, painter| {
-                shader_program.lock().unwrap().paint(painter.gl(), &mesh.lock().unwrap(), &camera.lock().unwrap(), |gl, program| {
-                    unsafe {
-                        gl.uniform_1_f32(
-                            gl.get_uniform_location(program, "u_Exp").as_ref(),
-                            exp
-                        );
-
-                        gl.uniform_1_u32(
-                            gl.get_uniform_location(program, "u_Iters").as_ref(),
-                            iters
-                        );
-
-                        gl.uniform_1_i32(
-                            gl.get_uniform_location(program, "u_Detail").as_ref(),
-                            detail
-                        );
+            callback: std::sync::Arc::new(egui_glow::CallbackFn::new(move |info, painter| {
+                let gl = painter.gl();
+
+                // export frames go through an offscreen target at their own resolution
+                if let Some((path, frame, width, height, export_cam)) = &export_frame {
+                    let mut slot = framebuffer.lock().unwrap();
+                    let ready = match slot.as_mut() {
+                        Some(framebuffer) => framebuffer.resize(gl, *width, *height).map(|_| ()),
+                        None => Framebuffer::new(gl, *width, *height, ColorFormat::Rgba8).map(|framebuffer| *slot = Some(framebuffer)),
+                    };
+
+                    if let Err(err) = ready {
+                        if let Some(framebuffer) = slot.take() {
+                            framebuffer.destroy(gl);
+                        }
+                        *export_error.lock().unwrap() = Some(err.to_string());
+                    } else if let Some(framebuffer) = slot.as_ref() {
+                        unsafe {
+                            gl.disable(eframe::glow::SCISSOR_TEST);
+                            framebuffer.bind(gl);
+                            shader_program.lock().unwrap().paint(gl, &mesh.lock().unwrap(), export_cam, &set_uniforms);
+                            draw_models(gl, export_cam);
+
+                            let pixels = framebuffer.read_rgba(gl);
+                            bind_egui_target(gl, &info, painter);
+
+                            let saved = image::RgbaImage::from_raw(*width as u32, *height as u32, pixels)
+                                .ok_or_else(|| "framebuffer size mismatch".to_owned())
+                                .and_then(|img| render::save_png(&img, path).map_err(|e| format!("{}: {e}", path.display())));
+                            match saved {
+                                Ok(()) => *export_written.lock().unwrap() = Some(*frame),
+                                Err(err) => *export_error.lock().unwrap() = Some(err),
+                            }
+                        }
                     }
+                }
+
+                let program = shader_program.lock().unwrap();
+                let mesh = mesh.lock().unwrap();
+                let camera = camera.lock().unwrap();
+                let mut timer = gpu_timer.lock().unwrap();
+                timer.poll(gl);
+                if !offscreen {
+                    timer.time(gl, profiling, || program.paint(gl, &mesh, &camera, &set_uniforms));
+                    draw_models(gl, &camera);
+                    draw_debug(gl, &info, &camera);
+                    return;
+                }
+
+                let vp = info.viewport_in_pixels();
+                let mut slot = accumulator.lock().unwrap();
+                let ready = match slot.as_mut() {
+                    Some(accumulator) => Ok(accumulator),
+                    None => Accumulator::new(gl, vp.width_px, vp.height_px).map(|accumulator| slot.insert(accumulator)),
+                }
+                .and_then(|accumulator| {
+                    let moving = accumulator.track(SceneKey {
+                        program: program.program,
+                        view_proj: camera.get_proj_view_mat(),
+                        camera_pos: camera.pos,
+                        settings,
+                    });
+                    let scale = if moving { motion_scale } else { 1.0 };
+                    let size = |px: i32| ((px as f32 * scale).ceil() as i32).max(1);
+                    accumulator.resize(gl, size(vp.width_px), size(vp.height_px)).map(|()| accumulator)
                 });
+                let accumulator = match ready {
+                    Ok(accumulator) => accumulator,
+                    Err(err) => {
+                        if let Some(accumulator) = slot.take() {
+                            accumulator.destroy(gl);
+                        }
+                        *accumulation_error.lock().unwrap() = Some(err.to_string());
+                        timer.time(gl, profiling, || program.paint(gl, &mesh, &camera, &set_uniforms));
+                        draw_models(gl, &camera);
+                        draw_debug(gl, &info, &camera);
+                        return;
+                    }
+                };
+
+                if accumulator.samples() < max_samples {
+                    accumulator.accumulate(gl, |jitter| {
+                        timer.time(gl, profiling, || {
+                            program.paint(gl, &mesh, &camera, |gl, uniforms| {
+                                set_uniforms(gl, uniforms);
+                                uniforms.set(gl, "u_Jitter", jitter);
+                            });
+                        });
+                    });
+                    unsafe {
+                        bind_egui_target(gl, &info, painter);
+                    }
+                }
+                accumulator.present(gl, &mesh);
+                draw_models(gl, &camera);
+                draw_debug(gl, &info, &camera);
             })),
         };
         ui.painter().add(callback);
@@ -1,6 +1,8 @@
 use eframe::glow::{self, HasContext as _};
 use nalgebra::{Vector2, Vector3, Vector4};
 
+use crate::error::GlError;
+
 
 
 #[derive(Debug)]
@@ -20,7 +22,7 @@ pub struct Mesh {
 
 
 impl Mesh {
-    pub fn new(gl: &glow::Context, positions: Vec<Vector3<f32>>, indicies: Vec<u32>, uvs: Vec<Vector2<f32>>, wireframe: bool) -> Self {
+    pub fn new(gl: &glow::Context, positions: Vec<Vector3<f32>>, indicies: Vec<u32>, uvs: Vec<Vector2<f32>>, wireframe: bool) -> Result<Self, GlError> {
         use glow::HasContext as _;
 
         unsafe {
@@ -46,12 +48,12 @@ impl Mesh {
                 }
             }
 
-            let position_buffer: glow::NativeBuffer = gl.create_buffer().expect("Cannot create position buffer");
-            let color_buffer = gl.create_buffer().expect("Cannot create color buffer");
-            let uv_buffer = gl.create_buffer().expect("Cannot create uv buffer");
-            let index_buffer = gl.create_buffer().expect("Cannot create index buffer");
+            let position_buffer: glow::NativeBuffer = gl.create_buffer().map_err(GlError::resource("position buffer"))?;
+            let color_buffer = gl.create_buffer().map_err(GlError::resource("color buffer"))?;
+            let uv_buffer = gl.create_buffer().map_err(GlError::resource("uv buffer"))?;
+            let index_buffer = gl.create_buffer().map_err(GlError::resource("index buffer"))?;
 
-            let vertex_array = gl.create_vertex_array().expect("Cannot create vertex array");
+            let vertex_array = gl.create_vertex_array().map_err(GlError::resource("vertex array"))?;
 
             let mut x = Self {
                 positions: positions.clone(), 
@@ -69,30 +71,24 @@ impl Mesh {
 
             x.load_buffers(gl);
 
-            x
+            Ok(x)
         }
     }
 
 
+    /// Uploads the vertex data into the buffers `new` created.
     pub fn load_buffers(&mut self, gl: &glow::Context) {
         unsafe {
-            self.position_buffer = gl.create_buffer().expect("Cannot create position buffer");
-            self.color_buffer = gl.create_buffer().expect("Cannot create color buffer");
-            self.uv_buffer = gl.create_buffer().expect("Cannot create uv buffer");
-            self.index_buffer = gl.create_buffer().expect("Cannot create index buffer");
-
-            self.vertex_array = gl.create_vertex_array().expect("Cannot create vertex array");
-
             // gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
             gl.bind_vertex_array(Some(self.vertex_array));
             gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.index_buffer));
-            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, bytemuck::cast_slice(&self.indicies.chunks_exact(3).map(|x| {
+            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, bytemuck::cast_slice(&self.indicies.chunks_exact(3).flat_map(|x| {
                 if self.wireframe {
                     [x[0], x[1], x[1], x[2], x[2], x[0]].to_vec()
                 } else {
                     [x[0], x[1], x[2]].to_vec()
                 }
-            } ).flatten().collect::<Vec<u32>>()), glow::STATIC_DRAW);
+            } ).collect::<Vec<u32>>()), glow::STATIC_DRAW);
 
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.position_buffer));
             gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytemuck::cast_slice(&self.positions.iter().flat_map(|x| {
@@ -124,7 +120,7 @@ impl Mesh {
         }
     }
 
-    pub fn _destroy(&self, gl: &glow::Context) {
+    pub fn destroy(&self, gl: &glow::Context) {
         use glow::HasContext as _;
         unsafe {
             gl.delete_buffer(self.position_buffer);
@@ -0,0 +1,490 @@
+use std::{fmt, fs::File, io::{self, BufWriter, Write}, path::Path, thread};
+
+use nalgebra::Vector3;
+
+use crate::{render::RenderSettings, sdf::{self, DistanceEstimator}, sdf_graph::GraphError};
+
+// Triangle meshes of the distance field, for 3D printing and DCC tools. The field is
+// sampled on a regular grid and polygonized with naive surface nets, the simplest form of
+// dual contouring: one vertex per cell the surface passes through, placed at the mean of
+// its edge crossings, and one quad per grid edge the surface crosses.
+//
+// The fractal estimators are only bounds, and some (the Mandelbox) never go negative, so
+// the surface is taken at half a cell out rather than at zero. That also closes up
+// details smaller than the grid can resolve. Where the bounding box cuts the surface the
+// mesh is capped, so it is always closed.
+
+
+/// Largest grid resolution accepted, which already takes about 4 GB of samples.
+pub const MAX_RESOLUTION: u32 = 1024;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    Obj,
+    /// Binary STL. Has no per-vertex normals or colors, so only face normals are written
+    Stl,
+    /// Binary little-endian PLY
+    Ply,
+}
+
+impl MeshFormat {
+    pub const ALL: [MeshFormat; 3] = [MeshFormat::Obj, MeshFormat::Stl, MeshFormat::Ply];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MeshFormat::Obj => "obj",
+            MeshFormat::Stl => "stl",
+            MeshFormat::Ply => "ply",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        MeshFormat::ALL.into_iter().find(|format| format.extension() == ext)
+    }
+}
+
+
+#[derive(Debug)]
+pub enum MeshError {
+    /// The path doesn't end in .obj, .stl or .ply
+    UnknownFormat(String),
+    Resolution(u32),
+    EmptyBounds,
+    /// No cell of the grid contains surface
+    NoSurface,
+    Graph(GraphError),
+    Io(io::Error),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::UnknownFormat(path) => write!(f, "can't tell the mesh format of '{path}', use .obj, .stl or .ply"),
+            MeshError::Resolution(res) => write!(f, "resolution {res} is outside 2..={MAX_RESOLUTION}"),
+            MeshError::EmptyBounds => write!(f, "the bounding box is empty"),
+            MeshError::NoSurface => write!(f, "no surface inside the bounding box"),
+            MeshError::Graph(err) => write!(f, "invalid scene graph: {err}"),
+            MeshError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl From<io::Error> for MeshError {
+    fn from(err: io::Error) -> Self {
+        MeshError::Io(err)
+    }
+}
+
+
+/// Region and density of the sampling grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshSettings {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+    /// Cells along the longest side of the box. The others get as many as keeps the
+    /// cells cubic
+    pub resolution: u32,
+}
+
+impl Default for MeshSettings {
+    fn default() -> Self {
+        Self {
+            min: Vector3::repeat(-1.5),
+            max: Vector3::repeat(1.5),
+            resolution: 128,
+        }
+    }
+}
+
+impl MeshSettings {
+    fn cell_size(&self) -> f32 {
+        (self.max - self.min).max() / self.resolution as f32
+    }
+
+    /// Cells along each axis.
+    fn cells(&self) -> [usize; 3] {
+        let size = self.cell_size();
+        let extent = self.max - self.min;
+        [0, 1, 2].map(|axis| ((extent[axis] / size).round() as usize).max(1))
+    }
+
+    fn validate(&self) -> Result<(), MeshError> {
+        if !(2..=MAX_RESOLUTION).contains(&self.resolution) {
+            return Err(MeshError::Resolution(self.resolution));
+        }
+        if (self.max - self.min).iter().any(|&side| side.is_nan() || side <= 0.0) {
+            return Err(MeshError::EmptyBounds);
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    /// Linear RGB in 0..1 from the scene's coloring, before lighting
+    pub colors: Vec<Vector3<f32>>,
+    /// Counter-clockwise seen from outside
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl TriangleMesh {
+    /// Writes the mesh in the format given by the path's extension.
+    pub fn save(&self, path: &Path) -> Result<(), MeshError> {
+        let format = MeshFormat::from_path(path).ok_or_else(|| MeshError::UnknownFormat(path.display().to_string()))?;
+        let mut out = BufWriter::new(File::create(path)?);
+        match format {
+            MeshFormat::Obj => self.write_obj(&mut out)?,
+            MeshFormat::Stl => self.write_stl(&mut out)?,
+            MeshFormat::Ply => self.write_ply(&mut out)?,
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Wavefront OBJ with vertex colors appended to the `v` lines, which Blender, MeshLab
+    /// and ZBrush read and everything else ignores.
+    pub fn write_obj(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "# {} vertices, {} triangles", self.positions.len(), self.triangles.len())?;
+        for (p, c) in self.positions.iter().zip(&self.colors) {
+            writeln!(out, "v {} {} {} {:.4} {:.4} {:.4}", p.x, p.y, p.z, c.x, c.y, c.z)?;
+        }
+        for n in &self.normals {
+            writeln!(out, "vn {:.5} {:.5} {:.5}", n.x, n.y, n.z)?;
+        }
+        for tri in &self.triangles {
+            let [a, b, c] = tri.map(|i| i + 1);
+            writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}")?;
+        }
+        Ok(())
+    }
+
+    pub fn write_stl(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut header = [0u8; 80];
+        let name = b"raymarcher distance field";
+        header[..name.len()].copy_from_slice(name);
+        out.write_all(&header)?;
+        out.write_all(&(self.triangles.len() as u32).to_le_bytes())?;
+
+        for tri in &self.triangles {
+            let [a, b, c] = tri.map(|i| self.positions[i as usize]);
+            let normal = (b - a).cross(&(c - a)).try_normalize(0.0).unwrap_or_default();
+            for v in [normal, a, b, c] {
+                for x in v.iter() {
+                    out.write_all(&x.to_le_bytes())?;
+                }
+            }
+            // attribute byte count
+            out.write_all(&[0, 0])?;
+        }
+        Ok(())
+    }
+
+    pub fn write_ply(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "ply")?;
+        writeln!(out, "format binary_little_endian 1.0")?;
+        writeln!(out, "element vertex {}", self.positions.len())?;
+        for prop in ["x", "y", "z", "nx", "ny", "nz"] {
+            writeln!(out, "property float {prop}")?;
+        }
+        for prop in ["red", "green", "blue"] {
+            writeln!(out, "property uchar {prop}")?;
+        }
+        writeln!(out, "element face {}", self.triangles.len())?;
+        writeln!(out, "property list uchar uint vertex_indices")?;
+        writeln!(out, "end_header")?;
+
+        for ((p, n), c) in self.positions.iter().zip(&self.normals).zip(&self.colors) {
+            for x in p.iter().chain(n.iter()) {
+                out.write_all(&x.to_le_bytes())?;
+            }
+            out.write_all(&[c.x, c.y, c.z].map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8))?;
+        }
+        for tri in &self.triangles {
+            out.write_all(&[3])?;
+            for i in tri {
+                out.write_all(&i.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Polygonizes the scene's distance field inside the box. Sampling is split across all
+/// available cores like `render::render`.
+pub fn extract(settings: &RenderSettings, mesh: &MeshSettings) -> Result<TriangleMesh, MeshError> {
+    mesh.validate()?;
+    // a zero plane normal or NaN parameter would put NaN vertices in the mesh
+    settings.graph.validate().map_err(MeshError::Graph)?;
+
+    let de = DistanceEstimator {
+        kind: settings.fractal,
+        power: settings.exp,
+        iterations: settings.iterations,
+        params: settings.fractal_params,
+        traps: settings.orbit_traps.packed(),
+    };
+    let map = |p| settings.graph.distance(p, &de);
+
+    let cells = mesh.cells();
+    let step = mesh.cell_size();
+    let iso = 0.5 * step;
+    let [nx, ny, nz] = cells.map(|n| n + 1);
+    let sample_pos = |x: usize, y: usize, z: usize| mesh.min + Vector3::new(x as f32, y as f32, z as f32) * step;
+    let sample_index = |x: usize, y: usize, z: usize| x + nx * (y + ny * z);
+
+    // distance at every grid point, a z slice per task
+    let mut samples = vec![0.0f32; nx * ny * nz];
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let slices_per_chunk = nz.div_ceil(threads).max(1);
+    thread::scope(|s| {
+        for (chunk_idx, chunk) in samples.chunks_mut(slices_per_chunk * nx * ny).enumerate() {
+            let map = &map;
+            s.spawn(move || {
+                for (i, d) in chunk.iter_mut().enumerate() {
+                    let z = chunk_idx * slices_per_chunk + i / (nx * ny);
+                    let (x, y) = (i % nx, (i / nx) % ny);
+                    let distance = map(sample_pos(x, y, z)).0;
+                    *d = if x == 0 || y == 0 || z == 0 || x == nx - 1 || y == ny - 1 || z == nz - 1 {
+                        // outside on the faces of the box, so a box through the fractal
+                        // gives a closed mesh with flat caps
+                        distance.max(iso) - iso + 0.5 * step
+                    } else if distance.is_nan() {
+                        // the bulb's estimate is NaN where an orbit lands exactly on the
+                        // origin, which only happens deep inside
+                        -step
+                    } else {
+                        distance - iso
+                    };
+                }
+            });
+        }
+    });
+
+    // one vertex per cell with a sign change
+    let cell_index = |x: usize, y: usize, z: usize| x + cells[0] * (y + cells[1] * z);
+    let mut cell_vertex = vec![u32::MAX; cells[0] * cells[1] * cells[2]];
+    let mut positions = Vec::new();
+    for z in 0..cells[2] {
+        for y in 0..cells[1] {
+            for x in 0..cells[0] {
+                let corner = |c: usize| (x + (c & 1), y + ((c >> 1) & 1), z + (c >> 2));
+                let d: [f32; 8] = std::array::from_fn(|c| {
+                    let (cx, cy, cz) = corner(c);
+                    samples[sample_index(cx, cy, cz)]
+                });
+                if d.iter().all(|&v| v < 0.0) || d.iter().all(|&v| v >= 0.0) {
+                    continue;
+                }
+
+                // mean of the points where the 12 cell edges cross the surface
+                let mut sum = Vector3::zeros();
+                let mut crossings = 0;
+                for a in 0..8 {
+                    for bit in [1, 2, 4] {
+                        let b = a | bit;
+                        if b == a || (d[a] < 0.0) == (d[b] < 0.0) {
+                            continue;
+                        }
+                        let t = d[a] / (d[a] - d[b]);
+                        let (ax, ay, az) = corner(a);
+                        let (bx, by, bz) = corner(b);
+                        sum += sample_pos(ax, ay, az).lerp(&sample_pos(bx, by, bz), t);
+                        crossings += 1;
+                    }
+                }
+                cell_vertex[cell_index(x, y, z)] = positions.len() as u32;
+                positions.push(sum / crossings as f32);
+            }
+        }
+    }
+    if positions.is_empty() {
+        return Err(MeshError::NoSurface);
+    }
+
+    // a quad between the four cells around every crossed grid edge. The edge's other two
+    // axes, taken cyclically, span the quad so it faces along the edge
+    let mut triangles = Vec::new();
+    for z in 0..nz {
+        for y in 0..ny {
+            for x in 0..nx {
+                let p = [x, y, z];
+                let inside = samples[sample_index(x, y, z)] < 0.0;
+                for axis in 0..3 {
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                    if p[axis] + 1 >= [nx, ny, nz][axis] || p[u] == 0 || p[v] == 0 || p[u] > cells[u] - 1 || p[v] > cells[v] - 1 {
+                        continue;
+                    }
+                    let mut next = p;
+                    next[axis] += 1;
+                    if inside == (samples[sample_index(next[0], next[1], next[2])] < 0.0) {
+                        continue;
+                    }
+
+                    let quad = [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(du, dv)| {
+                        let mut c = p;
+                        c[u] = c[u] - 1 + du;
+                        c[v] = c[v] - 1 + dv;
+                        cell_vertex[cell_index(c[0], c[1], c[2])]
+                    });
+                    // the field grows outward, so an inside start means the quad faces +axis
+                    let [a, b, c, d] = if inside { quad } else { [quad[0], quad[3], quad[2], quad[1]] };
+                    triangles.push([a, b, c]);
+                    triangles.push([a, c, d]);
+                }
+            }
+        }
+    }
+
+    let normals: Vec<_> = positions.iter().map(|&p| sdf::normal(p, |q| map(q).0)).collect();
+    let colors = positions
+        .iter()
+        .zip(&normals)
+        .map(|(&p, &n)| settings.coloring.albedo(&settings.palette, n, &map(p).1, settings.iterations, p))
+        .collect();
+
+    Ok(TriangleMesh { positions, normals, colors, triangles })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::sdf_graph::SdfNode;
+
+    fn sphere_mesh() -> TriangleMesh {
+        let settings = RenderSettings { graph: SdfNode::Sphere { radius: 1.0 }, ..RenderSettings::default() };
+        extract(&settings, &MeshSettings { resolution: 16, ..MeshSettings::default() }).unwrap()
+    }
+
+    #[test]
+    fn sphere_is_closed_and_consistently_wound() {
+        let mesh = sphere_mesh();
+        assert!(!mesh.triangles.is_empty());
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+        assert_eq!(mesh.colors.len(), mesh.positions.len());
+
+        // every edge is used once in each direction
+        let mut edges = HashSet::new();
+        for &[a, b, c] in &mesh.triangles {
+            for edge in [(a, b), (b, c), (c, a)] {
+                assert!(edges.insert(edge), "edge {edge:?} used twice in the same direction");
+            }
+        }
+        assert!(edges.iter().all(|&(a, b)| edges.contains(&(b, a))));
+
+        // the surface is taken half a cell out
+        let radius = 1.0 + 0.5 * 3.0 / 16.0;
+        for p in &mesh.positions {
+            assert!((p.norm() - radius).abs() < 0.1, "{p}");
+        }
+
+        // counter-clockwise from outside encloses a positive volume
+        let volume: f32 = mesh.triangles.iter().map(|tri| {
+            let [a, b, c] = tri.map(|i| mesh.positions[i as usize]);
+            a.dot(&b.cross(&c)) / 6.0
+        }).sum();
+        let expected = 4.0 / 3.0 * std::f32::consts::PI * radius.powi(3);
+        assert!((volume - expected).abs() < 0.1 * expected, "{volume}");
+    }
+
+    #[test]
+    fn box_through_the_surface_gets_capped() {
+        let settings = RenderSettings { graph: SdfNode::Sphere { radius: 1.0 }, ..RenderSettings::default() };
+        let bounds = MeshSettings { min: Vector3::new(-1.5, -1.5, 0.0), max: Vector3::new(1.5, 1.5, 1.5), resolution: 16 };
+        let mesh = extract(&settings, &bounds).unwrap();
+
+        let mut edges = HashSet::new();
+        for &[a, b, c] in &mesh.triangles {
+            edges.extend([(a, b), (b, c), (c, a)]);
+        }
+        assert!(edges.iter().all(|&(a, b)| edges.contains(&(b, a))));
+    }
+
+    #[test]
+    fn stl_is_a_header_count_and_fifty_bytes_per_triangle() {
+        let mesh = sphere_mesh();
+        let mut bytes = Vec::new();
+        mesh.write_stl(&mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 84 + 50 * mesh.triangles.len());
+        assert!(bytes.starts_with(b"raymarcher distance field"));
+        assert_eq!(u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize, mesh.triangles.len());
+        // first vertex of the first triangle, after its normal
+        let [a, _, _] = mesh.triangles[0];
+        let x = f32::from_le_bytes(bytes[96..100].try_into().unwrap());
+        assert_eq!(x, mesh.positions[a as usize].x);
+    }
+
+    #[test]
+    fn ply_header_matches_the_binary_body() {
+        let mesh = TriangleMesh {
+            positions: vec![Vector3::zeros(), Vector3::x(), Vector3::y()],
+            normals: vec![Vector3::z(); 3],
+            colors: vec![Vector3::new(1.0, 0.5, 0.0), Vector3::zeros(), Vector3::repeat(2.0)],
+            triangles: vec![[0, 1, 2]],
+        };
+        let mut bytes = Vec::new();
+        mesh.write_ply(&mut bytes).unwrap();
+
+        let end = b"end_header\n";
+        let body = bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let header = std::str::from_utf8(&bytes[..body]).unwrap();
+        assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+        assert!(header.contains("element vertex 3\n"));
+        assert!(header.contains("element face 1\n"));
+
+        // 6 floats and 3 color bytes per vertex, a count byte and 3 indices per face
+        assert_eq!(bytes.len() - body, 3 * 27 + 13);
+        assert_eq!(bytes[body + 24..body + 27], [255, 128, 0]);
+        assert_eq!(bytes[body + 2 * 27 + 24..body + 3 * 27], [255, 255, 255]);
+        assert_eq!(bytes[body + 3 * 27], 3);
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(MeshFormat::from_path(Path::new("out/bulb.obj")), Some(MeshFormat::Obj));
+        assert_eq!(MeshFormat::from_path(Path::new("bulb.STL")), Some(MeshFormat::Stl));
+        assert_eq!(MeshFormat::from_path(Path::new("bulb.ply")), Some(MeshFormat::Ply));
+        assert_eq!(MeshFormat::from_path(Path::new("bulb.png")), None);
+        assert_eq!(MeshFormat::from_path(Path::new("bulb")), None);
+    }
+
+    #[test]
+    fn settings_reject_bad_resolutions_and_empty_boxes() {
+        let ok = MeshSettings::default();
+        assert!(ok.validate().is_ok());
+        assert!(MeshSettings { resolution: 2, ..ok }.validate().is_ok());
+        for resolution in [0, 1, MAX_RESOLUTION + 1] {
+            assert!(matches!(MeshSettings { resolution, ..ok }.validate(), Err(MeshError::Resolution(r)) if r == resolution));
+        }
+
+        let flat = MeshSettings { max: Vector3::new(1.5, 1.5, -1.5), ..ok };
+        assert!(matches!(flat.validate(), Err(MeshError::EmptyBounds)));
+        let nan = MeshSettings { min: Vector3::new(f32::NAN, 0.0, 0.0), ..ok };
+        assert!(matches!(nan.validate(), Err(MeshError::EmptyBounds)));
+    }
+
+    #[test]
+    fn invalid_graph_is_rejected_before_meshing() {
+        let graph = SdfNode::Plane { normal: Vector3::zeros(), offset: 0.0 };
+        let settings = RenderSettings { graph, ..RenderSettings::default() };
+        assert!(matches!(extract(&settings, &MeshSettings::default()), Err(MeshError::Graph(_))));
+    }
+
+    #[test]
+    fn empty_box_has_no_surface() {
+        let settings = RenderSettings { graph: SdfNode::Sphere { radius: 0.2 }, ..RenderSettings::default() };
+        let far = MeshSettings { min: Vector3::repeat(2.0), max: Vector3::repeat(3.0), resolution: 8 };
+        assert!(matches!(extract(&settings, &far), Err(MeshError::NoSurface)));
+    }
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+in vec3 fs_world;
+
+uniform vec3 u_Color;
+uniform vec3 u_CamPos;
+
+out vec4 frag_color;
+
+void main() {
+    // OBJ normals are often missing or inconsistent, so faces are shaded flat from the
+    // screen-space derivatives, lit from the camera
+    vec3 normal = normalize(cross(dFdx(fs_world), dFdy(fs_world)));
+    float diffuse = abs(dot(normal, normalize(u_CamPos - fs_world)));
+    frag_color = vec4(u_Color * (0.25 + 0.75 * diffuse), 1.0);
+}
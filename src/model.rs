@@ -0,0 +1,148 @@
+use std::{fmt, path::{Path, PathBuf}};
+
+use eframe::glow::{self, HasContext as _};
+use meshview::camera::Camera;
+use nalgebra::{Matrix4, Vector2, Vector3};
+
+use crate::{error::GlError, mesh::Mesh, shader::ShaderProgram};
+
+// Reference objects loaded from OBJ files and rasterized over the fractal. The raymarch
+// pass writes depth, so the two occlude each other. Models are edited from the UI thread
+// and uploaded lazily in the paint callback, where the GL context is.
+
+
+const MODEL_VERT: &str = include_str!("model.vert.glsl");
+const MODEL_FRAG: &str = include_str!("model.frag.glsl");
+
+
+#[derive(Debug)]
+pub enum ModelError {
+    Load { path: PathBuf, source: tobj::LoadError },
+    /// Parsed, but there was nothing to draw
+    NoFaces(PathBuf),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Load { path, source } => write!(f, "{}: {source}", path.display()),
+            ModelError::NoFaces(path) => write!(f, "{} has no faces", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModelError::Load { source, .. } => Some(source),
+            ModelError::NoFaces(_) => None,
+        }
+    }
+}
+
+
+pub struct Model {
+    pub name: String,
+    pub visible: bool,
+    pub position: Vector3<f32>,
+    pub scale: f32,
+    pub color: [f32; 3],
+    positions: Vec<Vector3<f32>>,
+    indices: Vec<u32>,
+    mesh: Option<Mesh>,
+}
+
+impl Model {
+    /// Reads every object in an OBJ file into one model. Faces are triangulated, and
+    /// materials and texture coordinates are ignored.
+    pub fn load(path: &Path) -> Result<Self, ModelError> {
+        let (objects, _materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(|source| ModelError::Load { path: path.to_path_buf(), source })?;
+
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for object in objects {
+            let base = positions.len() as u32;
+            positions.extend(object.mesh.positions.chunks_exact(3).map(Vector3::from_column_slice));
+            indices.extend(object.mesh.indices.iter().map(|i| base + i));
+        }
+        if indices.is_empty() {
+            return Err(ModelError::NoFaces(path.to_path_buf()));
+        }
+
+        Ok(Self {
+            name: path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned()),
+            visible: true,
+            position: Vector3::zeros(),
+            scale: 1.0,
+            color: [0.8, 0.8, 0.8],
+            positions,
+            indices,
+            mesh: None,
+        })
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn transform(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.position) * Matrix4::new_scaling(self.scale)
+    }
+}
+
+
+/// The imported models and the GL state for drawing them.
+#[derive(Default)]
+pub struct ModelLayer {
+    pub models: Vec<Model>,
+    program: Option<ShaderProgram>,
+    /// Meshes of removed models, deleted on the next draw
+    retired: Vec<Mesh>,
+}
+
+impl ModelLayer {
+    pub fn remove(&mut self, index: usize) {
+        if let Some(mesh) = self.models.remove(index).mesh {
+            self.retired.push(mesh);
+        }
+    }
+
+    /// Draws the visible models into the bound framebuffer, testing against the depth
+    /// already in it.
+    pub fn draw(&mut self, gl: &glow::Context, camera: &Camera) -> Result<(), GlError> {
+        for mesh in self.retired.drain(..) {
+            mesh.destroy(gl);
+        }
+        if !self.models.iter().any(|model| model.visible) {
+            return Ok(());
+        }
+
+        let program = match &mut self.program {
+            Some(program) => program,
+            None => self.program.insert(ShaderProgram::new(gl, MODEL_VERT, MODEL_FRAG)?),
+        };
+
+        let view_proj = camera.get_proj_view_mat();
+        for model in self.models.iter_mut().filter(|model| model.visible) {
+            let (transform, color) = (model.transform(), Vector3::from(model.color));
+            let mesh = match &mut model.mesh {
+                Some(mesh) => mesh,
+                None => {
+                    let uvs = vec![Vector2::zeros(); model.positions.len()];
+                    model.mesh.insert(Mesh::new(gl, model.positions.clone(), model.indices.clone(), uvs, false)?)
+                }
+            };
+
+            program.draw_over(gl, mesh, |gl, uniforms| {
+                uniforms.set(gl, "u_ViewProj", view_proj);
+                uniforms.set(gl, "u_Model", transform);
+                uniforms.set(gl, "u_Color", color);
+                uniforms.set(gl, "u_CamPos", camera.pos);
+            });
+        }
+        unsafe {
+            gl.disable(glow::DEPTH_TEST);
+        }
+        Ok(())
+    }
+}
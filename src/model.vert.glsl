@@ -0,0 +1,16 @@
+#version 330 core
+
+// Imported models, see model.rs.
+
+layout(location = 0) in vec4 vs_pos;
+
+uniform mat4 u_ViewProj;
+uniform mat4 u_Model;
+
+out vec3 fs_world;
+
+void main() {
+    vec4 world = u_Model * vec4(vs_pos.xyz, 1.0);
+    fs_world = world.xyz;
+    gl_Position = u_ViewProj * world;
+}
@@ -0,0 +1,256 @@
+use nalgebra::{Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::sdf::{sd_box, sd_sphere};
+
+// Orbit traps record how close a fractal's orbit comes to some shape. Every trap feeds
+// one channel of a vec4: red, green, blue, or brightness in w. Traps sharing a channel
+// combine by taking the minimum. `PackedTraps::evaluate` mirrors `orbitTrap()` in
+// main.frag.glsl.
+
+/// Size of the trap uniform arrays in the shader.
+pub const MAX_TRAPS: usize = 4;
+
+/// Trap value of a channel no trap feeds.
+pub const NO_TRAP: f32 = 1000000.0;
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum TrapShape {
+    /// Signed distance to the sphere, negative inside
+    Sphere { center: Vector3<f32>, radius: f32 },
+    Point { center: Vector3<f32> },
+    Plane { normal: Vector3<f32>, offset: f32 },
+    /// Infinite line through `point`
+    Axis { point: Vector3<f32>, direction: Vector3<f32> },
+    /// The three axis-aligned lines through `center`
+    Cross { center: Vector3<f32> },
+    /// Distance to the box's surface
+    Box { center: Vector3<f32>, half_extents: Vector3<f32> },
+}
+
+impl TrapShape {
+    pub const NAMES: [&'static str; 6] = ["Sphere", "Point", "Plane", "Axis", "Cross", "Box"];
+
+    /// Position in `NAMES`, also the shader's `TRAP_*` value.
+    pub fn index(&self) -> usize {
+        match self {
+            TrapShape::Sphere { .. } => 0,
+            TrapShape::Point { .. } => 1,
+            TrapShape::Plane { .. } => 2,
+            TrapShape::Axis { .. } => 3,
+            TrapShape::Cross { .. } => 4,
+            TrapShape::Box { .. } => 5,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.index()]
+    }
+
+    /// A reasonable starting shape for each entry of `NAMES`.
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => TrapShape::Point { center: Vector3::zeros() },
+            2 => TrapShape::Plane { normal: Vector3::y(), offset: 0.0 },
+            3 => TrapShape::Axis { point: Vector3::zeros(), direction: Vector3::z() },
+            4 => TrapShape::Cross { center: Vector3::zeros() },
+            5 => TrapShape::Box { center: Vector3::zeros(), half_extents: Vector3::repeat(0.5) },
+            _ => TrapShape::Sphere { center: Vector3::zeros(), radius: 0.5 },
+        }
+    }
+}
+
+
+/// Which component of the trap vec4 a trap feeds. The discriminant is the component index.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrapChannel {
+    Red,
+    Green,
+    Blue,
+    /// What the normal and orbit trap coloring modes read
+    Brightness,
+}
+
+impl TrapChannel {
+    pub const ALL: [TrapChannel; 4] = [TrapChannel::Red, TrapChannel::Green, TrapChannel::Blue, TrapChannel::Brightness];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrapChannel::Red => "Red",
+            TrapChannel::Green => "Green",
+            TrapChannel::Blue => "Blue",
+            TrapChannel::Brightness => "Brightness",
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Trap {
+    pub shape: TrapShape,
+    pub channel: TrapChannel,
+    pub enabled: bool,
+}
+
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct OrbitTraps {
+    pub traps: Vec<Trap>,
+}
+
+impl Default for OrbitTraps {
+    /// The sphere of radius 0.5 on brightness the renderer has always used.
+    fn default() -> Self {
+        Self {
+            traps: vec![Trap {
+                shape: TrapShape::from_index(0),
+                channel: TrapChannel::Brightness,
+                enabled: true,
+            }],
+        }
+    }
+}
+
+
+/// Enabled traps in the layout of the `u_Trap*` uniforms. What `a` and `b` hold depends
+/// on the shape; directions and normals are normalized here.
+#[derive(Clone, Copy, Debug)]
+pub struct PackedTraps {
+    pub count: i32,
+    pub shapes: [i32; MAX_TRAPS],
+    pub channels: [i32; MAX_TRAPS],
+    pub a: [Vector4<f32>; MAX_TRAPS],
+    pub b: [Vector4<f32>; MAX_TRAPS],
+}
+
+impl OrbitTraps {
+    pub fn packed(&self) -> PackedTraps {
+        let mut packed = PackedTraps {
+            count: 0,
+            shapes: [0; MAX_TRAPS],
+            channels: [0; MAX_TRAPS],
+            a: [Vector4::zeros(); MAX_TRAPS],
+            b: [Vector4::zeros(); MAX_TRAPS],
+        };
+        for trap in self.traps.iter().filter(|trap| trap.enabled).take(MAX_TRAPS) {
+            let i = packed.count as usize;
+            let (a, b) = match trap.shape {
+                TrapShape::Sphere { center, radius } => (center.push(radius), Vector4::zeros()),
+                TrapShape::Point { center } | TrapShape::Cross { center } => (center.push(0.0), Vector4::zeros()),
+                TrapShape::Plane { normal, offset } => (normal.try_normalize(1.0e-6).unwrap_or(Vector3::y()).push(offset), Vector4::zeros()),
+                TrapShape::Axis { point, direction } => (point.push(0.0), direction.try_normalize(1.0e-6).unwrap_or(Vector3::z()).push(0.0)),
+                TrapShape::Box { center, half_extents } => (center.push(0.0), half_extents.push(0.0)),
+            };
+            packed.shapes[i] = trap.shape.index() as i32;
+            packed.channels[i] = trap.channel as i32;
+            packed.a[i] = a;
+            packed.b[i] = b;
+            packed.count += 1;
+        }
+        packed
+    }
+}
+
+impl Default for PackedTraps {
+    fn default() -> Self {
+        OrbitTraps::default().packed()
+    }
+}
+
+impl PackedTraps {
+    /// Distance from `z` to every trap, per channel.
+    pub fn evaluate(&self, z: Vector3<f32>) -> Vector4<f32> {
+        let mut trap = Vector4::repeat(NO_TRAP);
+        for i in 0..self.count as usize {
+            let (a, b) = (self.a[i], self.b[i]);
+            let d = match self.shapes[i] {
+                1 => (z - a.xyz()).norm(),
+                2 => (z.dot(&a.xyz()) - a.w).abs(),
+                3 => (z - a.xyz()).cross(&b.xyz()).norm(),
+                4 => {
+                    let q = (z - a.xyz()).abs();
+                    q.yz().norm().min(q.xz().norm().min(q.xy().norm()))
+                }
+                5 => sd_box(z - a.xyz(), b.xyz()).abs(),
+                _ => sd_sphere(z - a.xyz(), a.w),
+            };
+            let c = self.channels[i] as usize;
+            trap[c] = trap[c].min(d);
+        }
+        trap
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traps(shapes: &[(TrapShape, TrapChannel)]) -> PackedTraps {
+        OrbitTraps {
+            traps: shapes.iter().map(|&(shape, channel)| Trap { shape, channel, enabled: true }).collect(),
+        }
+        .packed()
+    }
+
+    fn distance(shape: TrapShape, z: Vector3<f32>) -> f32 {
+        traps(&[(shape, TrapChannel::Red)]).evaluate(z).x
+    }
+
+    #[test]
+    fn every_shape_measures_its_distance() {
+        let z = Vector3::new(1.0, 2.0, 3.0);
+        let cases = [
+            (TrapShape::Sphere { center: Vector3::zeros(), radius: 1.0 }, 14.0_f32.sqrt() - 1.0),
+            (TrapShape::Sphere { center: z, radius: 0.5 }, -0.5),
+            (TrapShape::Point { center: Vector3::new(1.0, 2.0, 0.0) }, 3.0),
+            // the normal gets normalized when packed
+            (TrapShape::Plane { normal: Vector3::new(0.0, 4.0, 0.0), offset: 0.5 }, 1.5),
+            (TrapShape::Axis { point: Vector3::new(0.0, 0.0, -7.0), direction: Vector3::new(0.0, 0.0, 2.0) }, 5.0_f32.sqrt()),
+            (TrapShape::Cross { center: Vector3::zeros() }, 5.0_f32.sqrt()),
+            (TrapShape::Box { center: Vector3::zeros(), half_extents: Vector3::repeat(1.0) }, 5.0_f32.sqrt()),
+            // inside the box the distance to the surface is still positive
+            (TrapShape::Box { center: Vector3::new(1.0, 2.0, 2.5), half_extents: Vector3::repeat(1.0) }, 0.5),
+        ];
+        for (shape, expected) in cases {
+            let d = distance(shape, z);
+            assert!((d - expected).abs() < 1.0e-5, "{}: {d} != {expected}", shape.name());
+        }
+    }
+
+    #[test]
+    fn traps_on_a_channel_take_the_minimum() {
+        let packed = traps(&[
+            (TrapShape::Point { center: Vector3::new(3.0, 0.0, 0.0) }, TrapChannel::Green),
+            (TrapShape::Point { center: Vector3::new(1.0, 0.0, 0.0) }, TrapChannel::Green),
+            (TrapShape::Point { center: Vector3::new(0.0, 0.0, 2.0) }, TrapChannel::Brightness),
+        ]);
+        assert_eq!(packed.evaluate(Vector3::zeros()), Vector4::new(NO_TRAP, 1.0, NO_TRAP, 2.0));
+    }
+
+    #[test]
+    fn only_enabled_traps_are_packed_up_to_the_limit() {
+        let mut orbit_traps = OrbitTraps {
+            traps: vec![Trap { shape: TrapShape::from_index(1), channel: TrapChannel::Red, enabled: true }; MAX_TRAPS + 2],
+        };
+        assert_eq!(orbit_traps.packed().count, MAX_TRAPS as i32);
+
+        orbit_traps.traps[0].enabled = false;
+        orbit_traps.traps[0].shape = TrapShape::from_index(2);
+        orbit_traps.traps.truncate(2);
+        let packed = orbit_traps.packed();
+        assert_eq!(packed.count, 1);
+        assert_eq!(packed.shapes[0], 1);
+        assert_eq!(OrbitTraps { traps: Vec::new() }.packed().evaluate(Vector3::zeros()), Vector4::repeat(NO_TRAP));
+    }
+
+    #[test]
+    fn degenerate_directions_fall_back_to_an_axis() {
+        let plane = TrapShape::Plane { normal: Vector3::zeros(), offset: 0.0 };
+        assert!((distance(plane, Vector3::new(5.0, 2.0, 5.0)) - 2.0).abs() < 1.0e-6);
+        let axis = TrapShape::Axis { point: Vector3::zeros(), direction: Vector3::zeros() };
+        assert!((distance(axis, Vector3::new(3.0, 4.0, 9.0)) - 5.0).abs() < 1.0e-6);
+    }
+}
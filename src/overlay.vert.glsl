@@ -0,0 +1,12 @@
+#version 330 core
+
+// Debug lines, see debug_overlay.rs.
+
+layout(location = 0) in vec4 vs_pos;
+
+uniform mat4 u_ViewProj;
+uniform mat4 u_Model;
+
+void main() {
+    gl_Position = u_ViewProj * u_Model * vec4(vs_pos.xyz, 1.0);
+}
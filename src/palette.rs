@@ -0,0 +1,341 @@
+use std::{f32::consts::TAU, fmt, fs, io, path::Path};
+
+use nalgebra::{Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::sdf::Orbit;
+
+// Surface coloring shared by the shader and the CPU renderer. `Coloring::albedo` mirrors
+// `albedo()` in main.frag.glsl.
+
+/// Size of the `u_GradientStops` array in the shader.
+pub const MAX_STOPS: usize = 8;
+
+
+/// What picks the surface color. The discriminant is the value of `u_ColorMode`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColoringMode {
+    /// The three palette colors blended by the normal, darkened by the orbit trap
+    #[default]
+    Normal,
+    OrbitTrap,
+    /// Iteration the fractal's orbit escaped at, as a fraction of the iteration limit
+    Iterations,
+    /// Distance of the hit from the origin
+    Distance,
+    /// Traps on the red, green and blue channels color the surface directly, brighter
+    /// where the orbit came closer
+    TrapChannels,
+}
+
+impl ColoringMode {
+    pub const ALL: [ColoringMode; 5] = [
+        ColoringMode::Normal,
+        ColoringMode::OrbitTrap,
+        ColoringMode::Iterations,
+        ColoringMode::Distance,
+        ColoringMode::TrapChannels,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColoringMode::Normal => "Normal",
+            ColoringMode::OrbitTrap => "Orbit trap",
+            ColoringMode::Iterations => "Iterations",
+            ColoringMode::Distance => "Distance",
+            ColoringMode::TrapChannels => "Trap channels",
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct GradientStop {
+    /// 0 to 1
+    pub position: f32,
+    pub color: Vector3<f32>,
+}
+
+
+/// Maps a value in 0..1 to a color.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Gradient {
+    /// Linear blend between stops. Values outside the stops take the nearest end color
+    Stops(Vec<GradientStop>),
+    /// `a + b * cos(2π(c * t + d))`, after Quilez
+    Cosine {
+        a: Vector3<f32>,
+        b: Vector3<f32>,
+        c: Vector3<f32>,
+        d: Vector3<f32>,
+    },
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Gradient::Stops(vec![
+            GradientStop { position: 0.0, color: Vector3::new(0.0, 0.0, 0.1) },
+            GradientStop { position: 1.0, color: Vector3::new(0.8, 0.5, 0.6) },
+        ])
+    }
+}
+
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(err) => write!(f, "{err}"),
+            PaletteError::Parse(err) => write!(f, "invalid palette: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+
+fn parse_hex(s: &str) -> Option<Vector3<f32>> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok().map(|v| v as f32 / 255.0);
+    Some(Vector3::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+
+impl Gradient {
+    pub fn rainbow() -> Self {
+        Gradient::Cosine {
+            a: Vector3::repeat(0.5),
+            b: Vector3::repeat(0.5),
+            c: Vector3::repeat(1.0),
+            d: Vector3::new(0.0, 0.33, 0.67),
+        }
+    }
+
+    pub fn fire() -> Self {
+        Gradient::Stops(vec![
+            GradientStop { position: 0.0, color: Vector3::new(0.05, 0.0, 0.0) },
+            GradientStop { position: 0.4, color: Vector3::new(0.8, 0.15, 0.0) },
+            GradientStop { position: 0.75, color: Vector3::new(1.0, 0.7, 0.1) },
+            GradientStop { position: 1.0, color: Vector3::new(1.0, 1.0, 0.8) },
+        ])
+    }
+
+    /// Reads a gradient saved as JSON, or a plain list of `#rrggbb` colors (one per line,
+    /// `//` comments allowed) spread evenly from 0 to 1.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PaletteError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(PaletteError::Io)?;
+
+        if path.extension().is_some_and(|ext| ext == "json") {
+            return serde_json::from_str(&text).map_err(|err| PaletteError::Parse(err.to_string()));
+        }
+
+        let colors = text
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| parse_hex(line).ok_or_else(|| PaletteError::Parse(format!("`{line}` is not a #rrggbb color"))))
+            .collect::<Result<Vec<_>, _>>()?;
+        if colors.is_empty() {
+            return Err(PaletteError::Parse("no colors".to_owned()));
+        }
+
+        let last = (colors.len() - 1).max(1) as f32;
+        Ok(Gradient::Stops(
+            colors
+                .into_iter()
+                .enumerate()
+                .map(|(i, color)| GradientStop { position: i as f32 / last, color })
+                .collect(),
+        ))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PaletteError> {
+        let text = serde_json::to_string_pretty(self).map_err(|err| PaletteError::Parse(err.to_string()))?;
+        fs::write(path, text).map_err(PaletteError::Io)
+    }
+
+    /// Up to `MAX_STOPS` stops sorted by position, packed as rgb plus the position in `w`.
+    pub fn packed_stops(&self) -> (i32, [Vector4<f32>; MAX_STOPS]) {
+        let mut packed = [Vector4::zeros(); MAX_STOPS];
+        let Gradient::Stops(stops) = self else {
+            return (0, packed);
+        };
+
+        let mut sorted: Vec<_> = stops.iter().take(MAX_STOPS).collect();
+        sorted.sort_by(|a, b| a.position.total_cmp(&b.position));
+        for (slot, stop) in packed.iter_mut().zip(&sorted) {
+            *slot = stop.color.push(stop.position);
+        }
+        (sorted.len() as i32, packed)
+    }
+
+    /// `a`, `b`, `c` and `d` of a cosine gradient, zeros otherwise.
+    pub fn packed_cosine(&self) -> [Vector3<f32>; 4] {
+        match *self {
+            Gradient::Cosine { a, b, c, d } => [a, b, c, d],
+            Gradient::Stops(_) => [Vector3::zeros(); 4],
+        }
+    }
+
+    pub fn sample(&self, t: f32) -> Vector3<f32> {
+        if let Gradient::Cosine { a, b, c, d } = self {
+            return a + b.component_mul(&(c * t + d).map(|x| (TAU * x).cos()));
+        }
+
+        let (count, stops) = self.packed_stops();
+        if count == 0 {
+            return Vector3::zeros();
+        }
+        let t = t.clamp(0.0, 1.0);
+        let mut color = stops[0].xyz();
+        for i in 1..count as usize {
+            let (a, b) = (stops[i - 1], stops[i]);
+            if t >= a.w {
+                let f = ((t - a.w) / (b.w - a.w).max(1.0e-5)).clamp(0.0, 1.0);
+                color = a.xyz().lerp(&b.xyz(), f);
+            }
+        }
+        color
+    }
+}
+
+
+/// Coloring mode and the gradient the non-normal modes run through. The value a mode
+/// produces is scaled and offset before it is looked up, so bands can be stretched or
+/// cycled.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Coloring {
+    pub mode: ColoringMode,
+    pub gradient: Gradient,
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Default for Coloring {
+    fn default() -> Self {
+        Self {
+            mode: ColoringMode::Normal,
+            gradient: Gradient::default(),
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Coloring {
+    /// Surface color before lighting. `normal` is the unit surface normal, `orbit` what the
+    /// fractal's orbit did at `hit_pos` and `max_iterations` the iteration limit.
+    pub fn albedo(&self, palette: &[Vector3<f32>; 3], normal: Vector3<f32>, orbit: &Orbit, max_iterations: u32, hit_pos: Vector3<f32>) -> Vector3<f32> {
+        let trap = orbit.trap;
+        let value = match self.mode {
+            ColoringMode::Normal => {
+                let n = normal.add_scalar(0.8) / 2.0;
+                let brightness = trap.w.clamp(0.0, 1.0);
+                return brightness * (palette[0] * n.x + palette[1] * n.y + palette[2] * n.z);
+            }
+            ColoringMode::OrbitTrap => trap.w,
+            ColoringMode::Iterations => orbit.iterations as f32 / max_iterations.max(1) as f32,
+            ColoringMode::Distance => hit_pos.norm(),
+            ColoringMode::TrapChannels => {
+                return (trap.xyz() * self.scale).add_scalar(self.offset).map(|c| 1.0 - c.clamp(0.0, 1.0));
+            }
+        };
+        self.gradient.sample(value * self.scale + self.offset)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_text(name: &str, text: &str) -> Result<Gradient, PaletteError> {
+        let path = std::env::temp_dir().join(format!("meshview_palette_{}_{name}", std::process::id()));
+        fs::write(&path, text).unwrap();
+        let gradient = Gradient::load(&path);
+        fs::remove_file(&path).unwrap();
+        gradient
+    }
+
+    fn stop(position: f32, color: Vector3<f32>) -> GradientStop {
+        GradientStop { position, color }
+    }
+
+    #[test]
+    fn hex_lists_spread_evenly_and_skip_comments() {
+        let text = "// warm\n#ff0000\n\n00FF00 // no hash is fine\n#0000ff\n";
+        let gradient = load_text("hex.txt", text).unwrap();
+        assert_eq!(
+            gradient,
+            Gradient::Stops(vec![stop(0.0, Vector3::x()), stop(0.5, Vector3::y()), stop(1.0, Vector3::z())])
+        );
+
+        let Gradient::Stops(single) = load_text("single.txt", "#808080").unwrap() else { panic!() };
+        assert_eq!(single, vec![stop(0.0, Vector3::repeat(128.0 / 255.0))]);
+    }
+
+    #[test]
+    fn bad_palette_files_are_rejected() {
+        for text in ["#ff00", "#ff00000", "#gg0000", "red", "// only a comment\n", ""] {
+            assert!(matches!(load_text("bad.txt", text), Err(PaletteError::Parse(_))), "{text:?}");
+        }
+        assert!(matches!(load_text("bad.json", "[1, 2]"), Err(PaletteError::Parse(_))));
+        assert!(matches!(Gradient::load("/nonexistent/palette.txt"), Err(PaletteError::Io(_))));
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let path = std::env::temp_dir().join(format!("meshview_palette_{}_round.json", std::process::id()));
+        for gradient in [Gradient::fire(), Gradient::rainbow()] {
+            gradient.save(&path).unwrap();
+            assert_eq!(Gradient::load(&path).unwrap(), gradient);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn stops_are_sorted_and_capped_when_packed() {
+        let gradient = Gradient::Stops(vec![stop(1.0, Vector3::z()), stop(0.0, Vector3::x()), stop(0.25, Vector3::y())]);
+        let (count, packed) = gradient.packed_stops();
+        assert_eq!(count, 3);
+        assert_eq!(&packed[..3], &[Vector4::new(1.0, 0.0, 0.0, 0.0), Vector4::new(0.0, 1.0, 0.0, 0.25), Vector4::new(0.0, 0.0, 1.0, 1.0)]);
+        assert!(packed[3..].iter().all(|slot| *slot == Vector4::zeros()));
+
+        let many = Gradient::Stops((0..MAX_STOPS + 3).map(|i| stop(i as f32 / 10.0, Vector3::zeros())).collect());
+        assert_eq!(many.packed_stops().0, MAX_STOPS as i32);
+        assert_eq!(Gradient::rainbow().packed_stops().0, 0);
+    }
+
+    #[test]
+    fn stops_clamp_at_the_ends_and_blend_between() {
+        let gradient = Gradient::Stops(vec![stop(0.2, Vector3::x()), stop(0.6, Vector3::y()), stop(1.0, Vector3::z())]);
+        for t in [-1.0, 0.0, 0.2] {
+            assert_eq!(gradient.sample(t), Vector3::x(), "{t}");
+        }
+        for t in [1.0, 3.0] {
+            assert_eq!(gradient.sample(t), Vector3::z(), "{t}");
+        }
+        assert!((gradient.sample(0.4) - Vector3::new(0.5, 0.5, 0.0)).norm() < 1.0e-6);
+        assert!((gradient.sample(0.9) - Vector3::new(0.0, 0.25, 0.75)).norm() < 1.0e-6);
+        assert_eq!(Gradient::Stops(Vec::new()).sample(0.5), Vector3::zeros());
+    }
+
+    #[test]
+    fn cosine_gradients_follow_the_formula() {
+        let gradient = Gradient::rainbow();
+        // a + b * cos(2π(t + d)) with a = b = 0.5 is 1 where t + d is whole
+        assert!((gradient.sample(0.0).x - 1.0).abs() < 1.0e-6);
+        assert!(gradient.sample(0.5).x.abs() < 1.0e-6);
+        assert!((gradient.sample(1.0) - gradient.sample(0.0)).norm() < 1.0e-5);
+    }
+}
@@ -0,0 +1,144 @@
+use eframe::egui::{self, DragValue, Sense};
+use meshview::palette::{Coloring, ColoringMode, Gradient, GradientStop, MAX_STOPS};
+use nalgebra::Vector3;
+
+use crate::widgets::vec3;
+
+
+fn color_button(ui: &mut egui::Ui, color: &mut Vector3<f32>) {
+    let mut rgb = [color.x, color.y, color.z];
+    ui.color_edit_button_rgb(&mut rgb);
+    *color = Vector3::from(rgb);
+}
+
+fn preview(ui: &mut egui::Ui, gradient: &Gradient) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 16.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let slices = 64;
+    for i in 0..slices {
+        let c = gradient.sample((i as f32 + 0.5) / slices as f32);
+        let x0 = rect.left() + rect.width() * i as f32 / slices as f32;
+        let x1 = rect.left() + rect.width() * (i + 1) as f32 / slices as f32;
+        let [r, g, b] = [c.x, c.y, c.z].map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let color = egui::Color32::from_rgb(r, g, b);
+        painter.rect_filled(egui::Rect::from_x_y_ranges(x0..=x1 + 0.5, rect.y_range()), 0.0, color);
+    }
+}
+
+fn stops_ui(ui: &mut egui::Ui, stops: &mut Vec<GradientStop>) {
+    let mut removed = None;
+    for (i, stop) in stops.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut stop.position).speed(0.005).range(0.0..=1.0));
+            color_button(ui, &mut stop.color);
+            if ui.small_button("x").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed.filter(|_| stops.len() > 1) {
+        stops.remove(i);
+    }
+
+    if ui.add_enabled(stops.len() < MAX_STOPS, egui::Button::new("Add stop")).clicked() {
+        // split the widest gap
+        let mut positions: Vec<f32> = stops.iter().map(|stop| stop.position).collect();
+        positions.sort_by(f32::total_cmp);
+        let (a, b) = positions
+            .windows(2)
+            .map(|w| (w[0], w[1]))
+            .max_by(|x, y| (x.1 - x.0).total_cmp(&(y.1 - y.0)))
+            .unwrap_or((0.0, 1.0));
+        let position = (a + b) / 2.0;
+        let color = Gradient::Stops(stops.clone()).sample(position);
+        stops.push(GradientStop { position, color });
+    }
+}
+
+
+/// Coloring mode, the normal-mode palette and the gradient editor. Returns a status
+/// message after a palette file was loaded or saved.
+pub fn coloring_ui(ui: &mut egui::Ui, coloring: &mut Coloring, palette: &mut [Vector3<f32>; 3]) -> Option<String> {
+    let mut status = None;
+
+    ui.horizontal(|ui| {
+        ui.label("Mode");
+        egui::ComboBox::from_id_salt("coloring_mode")
+            .selected_text(coloring.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in ColoringMode::ALL {
+                    ui.selectable_value(&mut coloring.mode, mode, mode.name());
+                }
+            });
+    });
+
+    if coloring.mode == ColoringMode::Normal {
+        ui.horizontal(|ui| {
+            ui.label("Palette (x, y, z)");
+            for color in palette.iter_mut() {
+                color_button(ui, color);
+            }
+        });
+        return None;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Scale");
+        ui.add(DragValue::new(&mut coloring.scale).speed(0.01));
+        ui.label("Offset");
+        ui.add(DragValue::new(&mut coloring.offset).speed(0.01));
+    });
+
+    preview(ui, &coloring.gradient);
+
+    ui.horizontal(|ui| {
+        let is_cosine = matches!(coloring.gradient, Gradient::Cosine { .. });
+        if ui.selectable_label(!is_cosine, "Stops").clicked() && is_cosine {
+            coloring.gradient = Gradient::default();
+        }
+        if ui.selectable_label(is_cosine, "Cosine").clicked() && !is_cosine {
+            coloring.gradient = Gradient::rainbow();
+        }
+        ui.separator();
+        if ui.button("Rainbow").clicked() {
+            coloring.gradient = Gradient::rainbow();
+        }
+        if ui.button("Fire").clicked() {
+            coloring.gradient = Gradient::fire();
+        }
+    });
+
+    match &mut coloring.gradient {
+        Gradient::Stops(stops) => stops_ui(ui, stops),
+        Gradient::Cosine { a, b, c, d } => {
+            vec3(ui, "a", a);
+            vec3(ui, "b", b);
+            vec3(ui, "c", c);
+            vec3(ui, "d", d);
+        }
+    }
+
+    ui.horizontal(|ui| {
+        if ui.button("Load palette...").clicked() {
+            if let Some(path) = rfd::FileDialog::new().add_filter("Palette", &["json", "txt", "hex"]).pick_file() {
+                status = Some(match Gradient::load(&path) {
+                    Ok(gradient) => {
+                        coloring.gradient = gradient;
+                        format!("Loaded {}", path.display())
+                    }
+                    Err(err) => format!("Could not open {}: {err}", path.display()),
+                });
+            }
+        }
+        if ui.button("Save palette...").clicked() {
+            if let Some(path) = rfd::FileDialog::new().add_filter("Palette", &["json"]).set_file_name("palette.json").save_file() {
+                status = Some(match coloring.gradient.save(&path) {
+                    Ok(()) => format!("Saved {}", path.display()),
+                    Err(err) => format!("Could not save {}: {err}", path.display()),
+                });
+            }
+        }
+    });
+
+    status
+}
@@ -0,0 +1,249 @@
+use std::{collections::VecDeque, fs::File, io::{self, BufWriter, Write as _}, path::{Path, PathBuf}, time::Instant};
+
+use eframe::{egui, glow::{self, HasContext as _}};
+use meshview::shader_params::ShaderParams;
+
+use crate::error::GlError;
+
+// Frame timing for tuning iteration counts and detail against performance. The GPU side
+// comes from GL_TIME_ELAPSED queries, read back a few frames late so the CPU never waits
+// on them.
+
+/// Queries in flight at once. When all are pending the frame is not timed.
+const QUERIES: usize = 4;
+
+/// Frames kept for the history graph.
+const HISTORY: usize = 240;
+
+const FRAME_COLOR: egui::Color32 = egui::Color32::from_rgb(120, 200, 255);
+const GPU_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 170, 80);
+
+
+/// Times GPU work with `GL_TIME_ELAPSED` queries.
+pub struct GpuTimer {
+    free: Vec<glow::Query>,
+    pending: VecDeque<glow::Query>,
+    latest: Option<f32>,
+}
+
+impl GpuTimer {
+    pub fn new(gl: &glow::Context) -> Result<Self, GlError> {
+        let mut free = Vec::with_capacity(QUERIES);
+        for _ in 0..QUERIES {
+            free.push(unsafe { gl.create_query() }.map_err(GlError::resource("timer query"))?);
+        }
+        Ok(Self { free, pending: VecDeque::new(), latest: None })
+    }
+
+    /// Runs `f`, timing the GL commands it issues if `enabled`. Queries can't nest, so
+    /// `f` must not time anything itself.
+    pub fn time(&mut self, gl: &glow::Context, enabled: bool, f: impl FnOnce()) {
+        let Some(query) = self.free.pop().filter(|_| enabled) else {
+            return f();
+        };
+        unsafe {
+            gl.begin_query(glow::TIME_ELAPSED, query);
+            f();
+            gl.end_query(glow::TIME_ELAPSED);
+        }
+        self.pending.push_back(query);
+    }
+
+    /// Collects the queries that have finished, oldest first.
+    pub fn poll(&mut self, gl: &glow::Context) {
+        while let Some(&query) = self.pending.front() {
+            unsafe {
+                if gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) == 0 {
+                    break;
+                }
+                // nanoseconds; u32 holds a bit over four seconds, which is plenty for a frame
+                let elapsed = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+                self.latest = Some(elapsed as f32 / 1.0e6);
+            }
+            self.pending.pop_front();
+            self.free.push(query);
+        }
+    }
+
+    /// Milliseconds of the most recent finished query, if one finished since the last call.
+    pub fn take_latest(&mut self) -> Option<f32> {
+        self.latest.take()
+    }
+}
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct FrameSample {
+    /// Time since the previous frame
+    pub frame_ms: f32,
+    /// Time spent in `App::update`
+    pub cpu_ms: f32,
+    pub gpu_ms: Option<f32>,
+    /// Size the scene was rendered at, before any upscaling
+    pub resolution: (i32, i32),
+}
+
+
+struct CsvLog {
+    path: PathBuf,
+    out: BufWriter<File>,
+    /// Uniform columns, fixed when the log was started
+    params: Vec<String>,
+}
+
+
+#[derive(Default)]
+pub struct Profiler {
+    pub visible: bool,
+    history: VecDeque<FrameSample>,
+    log: Option<CsvLog>,
+    frame: u64,
+    started: Option<Instant>,
+}
+
+impl Profiler {
+    /// Starts writing every frame to a CSV file, one column per uniform in `params`.
+    pub fn start_log(&mut self, path: &Path, params: &ShaderParams) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let params: Vec<String> = params.keys().cloned().collect();
+        write!(out, "frame,time_s,frame_ms,cpu_ms,gpu_ms,width,height")?;
+        for name in &params {
+            write!(out, ",{name}")?;
+        }
+        writeln!(out)?;
+
+        self.log = Some(CsvLog { path: path.to_owned(), out, params });
+        self.frame = 0;
+        self.started = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Flushes and closes the CSV file. Returns where it was written.
+    pub fn stop_log(&mut self) -> io::Result<Option<PathBuf>> {
+        match self.log.take() {
+            Some(mut log) => log.out.flush().map(|()| Some(log.path)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn log_path(&self) -> Option<&Path> {
+        self.log.as_ref().map(|log| log.path.as_path())
+    }
+
+    /// Adds a frame to the history, and to the CSV file if one is open. A write error
+    /// closes the file.
+    pub fn record(&mut self, sample: FrameSample, params: &ShaderParams) -> io::Result<()> {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+
+        let Some(log) = &mut self.log else {
+            return Ok(());
+        };
+        let time = self.started.map_or(0.0, |started| started.elapsed().as_secs_f64());
+        let gpu = sample.gpu_ms.map(|ms| format!("{ms:.3}")).unwrap_or_default();
+        let mut row = format!(
+            "{},{time:.4},{:.3},{:.3},{gpu},{},{}",
+            self.frame, sample.frame_ms, sample.cpu_ms, sample.resolution.0, sample.resolution.1
+        );
+        for name in &log.params {
+            row.push(',');
+            if let Some(value) = params.get(name) {
+                row.push_str(&value.to_string());
+            }
+        }
+        self.frame += 1;
+
+        if let Err(err) = writeln!(log.out, "{row}") {
+            self.log = None;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Floating window with the latest numbers, a frame-time graph and the uniforms.
+    /// Returns a status message after the CSV log was started or stopped.
+    pub fn overlay(&mut self, ctx: &egui::Context, params: &ShaderParams) -> Option<String> {
+        let mut status = None;
+        let mut open = self.visible;
+
+        egui::Window::new("Profiler").open(&mut open).default_width(240.0).resizable(false).show(ctx, |ui| {
+            if let Some(last) = self.history.back() {
+                let average = |f: fn(&FrameSample) -> f32| self.history.iter().map(f).sum::<f32>() / self.history.len() as f32;
+                let gpu: Vec<f32> = self.history.iter().filter_map(|sample| sample.gpu_ms).collect();
+                ui.monospace(format!("frame {:7.2} ms  ({:.0} fps)", average(|s| s.frame_ms), 1000.0 / average(|s| s.frame_ms)));
+                ui.monospace(format!("cpu   {:7.2} ms", average(|s| s.cpu_ms)));
+                if gpu.is_empty() {
+                    // nothing was drawn, e.g. once progressive AA has all its samples
+                    ui.monospace("gpu       idle");
+                } else {
+                    ui.monospace(format!("gpu   {:7.2} ms", gpu.iter().sum::<f32>() / gpu.len() as f32));
+                }
+                ui.monospace(format!("size  {} x {}", last.resolution.0, last.resolution.1));
+            }
+
+            self.graph(ui);
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new("frame").color(FRAME_COLOR).small());
+                ui.label(egui::RichText::new("gpu").color(GPU_COLOR).small());
+            });
+
+            ui.separator();
+            egui::Grid::new("profiler_params").show(ui, |ui| {
+                for (name, value) in params {
+                    ui.monospace(name);
+                    ui.monospace(value.to_string());
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            match self.log_path().map(Path::to_owned) {
+                Some(path) => {
+                    ui.label(format!("Logging to {}", path.display()));
+                    if ui.button("Stop log").clicked() {
+                        status = Some(match self.stop_log() {
+                            Ok(_) => format!("Wrote {}", path.display()),
+                            Err(err) => format!("Could not write {}: {err}", path.display()),
+                        });
+                    }
+                }
+                None => {
+                    if ui.button("Log to CSV...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().add_filter("CSV", &["csv"]).set_file_name("frames.csv").save_file() {
+                            if let Err(err) = self.start_log(&path, params) {
+                                status = Some(format!("Could not create {}: {err}", path.display()));
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        self.visible = open;
+        status
+    }
+
+    /// Frame and GPU times over the history, scaled to the slowest frame but at least
+    /// 33 ms so a steady 60 fps reads as half height.
+    fn graph(&self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 60.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let max = self.history.iter().map(|sample| sample.frame_ms.max(sample.gpu_ms.unwrap_or(0.0))).fold(33.3, f32::max);
+        let point = |i: usize, ms: f32| {
+            egui::pos2(
+                rect.left() + rect.width() * i as f32 / (HISTORY - 1) as f32,
+                rect.bottom() - rect.height() * (ms / max).min(1.0),
+            )
+        };
+
+        let frame: Vec<_> = self.history.iter().enumerate().map(|(i, sample)| point(i, sample.frame_ms)).collect();
+        let gpu: Vec<_> = self.history.iter().enumerate().filter_map(|(i, sample)| Some(point(i, sample.gpu_ms?))).collect();
+        painter.add(egui::Shape::line(frame, egui::Stroke::new(1.0, FRAME_COLOR)));
+        painter.add(egui::Shape::line(gpu, egui::Stroke::new(1.0, GPU_COLOR)));
+        painter.text(rect.left_top() + egui::vec2(4.0, 2.0), egui::Align2::LEFT_TOP, format!("{max:.0} ms"), egui::FontId::monospace(10.0), ui.visuals().weak_text_color());
+    }
+}
@@ -0,0 +1,230 @@
+use std::{fmt, path::Path, thread};
+
+use image::{ImageResult, RgbaImage};
+use nalgebra::{Vector3, Vector4};
+
+use crate::{camera::{Camera, FAR_PLANE}, lighting::Lighting, orbit_trap::OrbitTraps, palette::Coloring, sdf::{self, DistanceEstimator, FractalParams, FractalType}, sdf_graph::SdfNode};
+
+// Software version of the raymarch loop in main.frag.glsl, for machines without a GPU.
+
+/// Colors blended by the surface normal's x, y and z components in `ColoringMode::Normal`.
+pub const DEFAULT_PALETTE: [Vector3<f32>; 3] = [
+    Vector3::new(1.0, 0.4, 0.6),
+    Vector3::new(0.3, 0.1, 0.8),
+    Vector3::new(0.9, 0.6, 0.6),
+];
+
+#[derive(Clone, PartialEq)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub exp: f32,
+    pub iterations: u32,
+    pub detail: i32,
+    pub palette: [Vector3<f32>; 3],
+    pub fractal: FractalType,
+    pub fractal_params: FractalParams,
+    pub graph: SdfNode,
+    pub lighting: Lighting,
+    pub coloring: Coloring,
+    pub orbit_traps: OrbitTraps,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 420,
+            height: 600,
+            exp: 8.0,
+            iterations: 12,
+            detail: 1,
+            palette: DEFAULT_PALETTE,
+            fractal: FractalType::Mandelbulb,
+            fractal_params: FractalParams::default(),
+            graph: SdfNode::Fractal,
+            lighting: Lighting::default(),
+            coloring: Coloring::default(),
+            orbit_traps: OrbitTraps::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    /// Width or height is zero
+    EmptyImage { width: u32, height: u32 },
+    /// The camera's view-projection matrix can't be inverted to cast rays
+    DegenerateCamera,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::EmptyImage { width, height } => write!(f, "can't render a {width}x{height} image"),
+            RenderError::DegenerateCamera => write!(f, "the camera's projection can't be inverted"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Same cap as MAX_STEPS in the shader. The tiling fractals can otherwise crawl along
+/// grazing rays for a very long time.
+const MAX_STEPS: u32 = 2000;
+
+struct Ray {
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+}
+
+impl Ray {
+    fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + t * self.direction
+    }
+}
+
+/// Renders one frame on the CPU. Rows are split across all available cores.
+pub fn render(camera: &Camera, settings: &RenderSettings) -> Result<RgbaImage, RenderError> {
+    let (width, height) = (settings.width, settings.height);
+    // a zero aspect ratio would panic in the projection
+    if width == 0 || height == 0 {
+        return Err(RenderError::EmptyImage { width, height });
+    }
+
+    let mut camera = camera.clone();
+    camera.aspect_ratio = width as f32 / height as f32;
+    let inv_view_proj = camera.get_proj_view_mat().try_inverse().ok_or(RenderError::DegenerateCamera)?;
+
+    let mut img = RgbaImage::new(width, height);
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_chunk = (height as usize).div_ceil(threads).max(1);
+    let row_bytes = width as usize * 4;
+
+    thread::scope(|s| {
+        for (chunk_idx, chunk) in img.chunks_mut(rows_per_chunk * row_bytes).enumerate() {
+            let camera = &camera;
+            s.spawn(move || {
+                for (i, px) in chunk.chunks_exact_mut(4).enumerate() {
+                    let x = (i % width as usize) as f32;
+                    let y = (chunk_idx * rows_per_chunk + i / width as usize) as f32;
+
+                    // pixel center -> the fs_uv the fullscreen quad would interpolate
+                    let uv_x = (x + 0.5) / width as f32 * 2.0 - 1.0;
+                    let uv_y = 1.0 - (y + 0.5) / height as f32 * 2.0;
+
+                    let near = inv_view_proj * Vector4::new(uv_x, uv_y, -1.0, 1.0);
+                    let far = inv_view_proj * Vector4::new(uv_x, uv_y, 1.0, 1.0);
+                    let dir = (far.xyz() / far.w - near.xyz() / near.w).normalize();
+
+                    let ray = Ray { origin: camera.pos, direction: dir };
+                    let col = shade_ray(&ray, settings);
+
+                    px.copy_from_slice(&[
+                        (col.x.clamp(0.0, 1.0) * 255.0).round() as u8,
+                        (col.y.clamp(0.0, 1.0) * 255.0).round() as u8,
+                        (col.z.clamp(0.0, 1.0) * 255.0).round() as u8,
+                        255,
+                    ]);
+                }
+            });
+        }
+    });
+
+    Ok(img)
+}
+
+fn shade_ray(ray: &Ray, settings: &RenderSettings) -> Vector3<f32> {
+    let de = DistanceEstimator {
+        kind: settings.fractal,
+        power: settings.exp,
+        iterations: settings.iterations,
+        params: settings.fractal_params,
+        traps: settings.orbit_traps.packed(),
+    };
+    let map = |p| settings.graph.distance(p, &de);
+    let hit_delta = hit_delta(settings.detail);
+
+    let mut t = 0.0;
+    let mut steps = 0;
+    while t < FAR_PLANE && steps < MAX_STEPS {
+        let (hit_dist, orbit) = map(ray.at(t));
+
+        if hit_dist < hit_delta {
+            let hit_pos = ray.at(t);
+            let normal = sdf::normal(hit_pos, |p| map(p).0);
+            let albedo = settings.coloring.albedo(&settings.palette, normal, &orbit, settings.iterations, hit_pos);
+            return settings.lighting.shade(albedo, hit_pos, normal, ray.direction, |p| map(p).0);
+        }
+        t += hit_dist;
+        steps += 1;
+    }
+
+    Vector3::new(0.1, 0.15, 0.25)
+}
+
+/// Distance below which a ray counts as hitting the surface, same as `hit_delta` in the
+/// shader. Done in floats, as `10000 * detail` overflows an i32 for large details.
+fn hit_delta(detail: i32) -> f32 {
+    1.0 / (10000.0 * detail as f32)
+}
+
+pub fn save_png(img: &RgbaImage, path: impl AsRef<Path>) -> ImageResult<()> {
+    img.save_with_format(path, image::ImageFormat::Png)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKGROUND: [u8; 4] = [26, 38, 64, 255];
+
+    fn small() -> RenderSettings {
+        RenderSettings { width: 24, height: 16, ..RenderSettings::default() }
+    }
+
+    #[test]
+    fn renders_at_the_requested_size() {
+        let img = render(&Camera::default(), &small()).unwrap();
+        assert_eq!(img.dimensions(), (24, 16));
+    }
+
+    #[test]
+    fn empty_image_is_an_error() {
+        for (width, height) in [(0, 16), (24, 0)] {
+            let settings = RenderSettings { width, height, ..RenderSettings::default() };
+            assert!(matches!(render(&Camera::default(), &settings), Err(RenderError::EmptyImage { .. })));
+        }
+    }
+
+    #[test]
+    fn fractal_fills_the_center_and_misses_the_corners() {
+        let img = render(&Camera::default(), &small()).unwrap();
+        assert_ne!(img.get_pixel(12, 8).0, BACKGROUND);
+        assert_eq!(img.get_pixel(0, 0).0, BACKGROUND);
+        assert_eq!(img.get_pixel(23, 15).0, BACKGROUND);
+    }
+
+    #[test]
+    fn camera_facing_away_sees_only_background() {
+        let mut camera = Camera::default();
+        camera.set_euler_angles((0.0, 180.0, 0.0));
+        let img = render(&camera, &small()).unwrap();
+        assert!(img.pixels().all(|px| px.0 == BACKGROUND));
+    }
+
+    #[test]
+    fn hit_delta_survives_large_details() {
+        for detail in [1, 300000, i32::MAX] {
+            let delta = hit_delta(detail);
+            assert!(delta.is_finite() && delta > 0.0, "{detail}: {delta}");
+        }
+    }
+
+    #[test]
+    fn rendering_is_deterministic() {
+        let settings = small();
+        assert_eq!(render(&Camera::default(), &settings).unwrap(), render(&Camera::default(), &settings).unwrap());
+    }
+}
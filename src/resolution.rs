@@ -0,0 +1,42 @@
+// Dynamic resolution. While the view is changing, the viewport is rendered at a fraction
+// of its size that is nudged every frame toward the target frame rate, then stretched to
+// fill the canvas. Still frames always render at full size.
+
+/// Lowest fraction of the viewport size rendered while moving.
+const MIN_SCALE: f32 = 0.2;
+
+
+pub struct ResolutionScaler {
+    pub enabled: bool,
+    pub target_fps: f32,
+    scale: f32,
+}
+
+impl Default for ResolutionScaler {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target_fps: 30.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl ResolutionScaler {
+    /// Fraction of the viewport's width and height to render while moving.
+    pub fn scale(&self) -> f32 {
+        if self.enabled { self.scale } else { 1.0 }
+    }
+
+    /// Adjusts the scale after a frame that took `dt` seconds. Only frames rendered while
+    /// `moving` say anything about the scaled cost, so the others are ignored.
+    pub fn update(&mut self, dt: f32, moving: bool) {
+        if !self.enabled || !moving || dt <= 0.0 {
+            return;
+        }
+        // the pixel count, and with it the frame time, goes with the square of the scale.
+        // The step is limited so one slow frame doesn't make the image jump
+        let ratio = (1.0 / (self.target_fps * dt)).sqrt().clamp(0.8, 1.25);
+        self.scale = (self.scale * ratio).clamp(MIN_SCALE, 1.0);
+    }
+}
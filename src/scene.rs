@@ -0,0 +1,208 @@
+use std::{fmt, fs, io, path::Path};
+
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{animation::{Timeline, TimelineSample}, bookmark::Bookmark, camera::Camera, lighting::Lighting, orbit_trap::OrbitTraps, palette::Coloring, render::{RenderSettings, DEFAULT_PALETTE}, sdf::{FractalParams, FractalType}, sdf_graph::SdfNode, shader_params::ShaderParams};
+
+
+/// Everything needed to reproduce a view, saved as JSON. Missing keys fall back to the
+/// defaults so older or hand-written files still load.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Scene {
+    pub position: Vector3<f32>,
+    /// Euler angles in degrees. Still written so older versions can read the file, but
+    /// `orientation` wins when present
+    pub angle: (f32, f32, f32),
+    /// Exact camera orientation, see `Camera::orientation`. Files from before it existed
+    /// only have `angle`
+    pub orientation: Option<UnitQuaternion<f32>>,
+    pub speed: f32,
+    pub exp: f32,
+    pub num_iters: u32,
+    pub detail: i32,
+    pub animating: bool,
+    pub fractal: FractalType,
+    pub fractal_params: FractalParams,
+    /// Geometry combined with the fractal, see `SdfNode`
+    pub graph: SdfNode,
+    pub palette: [Vector3<f32>; 3],
+    pub coloring: Coloring,
+    pub orbit_traps: OrbitTraps,
+    pub lighting: Lighting,
+    pub bookmarks: Vec<Bookmark>,
+    pub timeline: Timeline,
+    /// Values for `// @ui` uniforms in the shader that have no field above. Only the
+    /// GPU renderer uses these
+    pub shader_params: ShaderParams,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            position: Vector3::new(0.0, 0.0, 3.0),
+            angle: (0.0, 0.0, 0.0),
+            orientation: None,
+            speed: 1.0,
+            exp: 8.0,
+            num_iters: 12,
+            detail: 1,
+            animating: false,
+            fractal: FractalType::Mandelbulb,
+            fractal_params: FractalParams::default(),
+            graph: SdfNode::Fractal,
+            palette: DEFAULT_PALETTE,
+            coloring: Coloring::default(),
+            orbit_traps: OrbitTraps::default(),
+            lighting: Lighting::default(),
+            bookmarks: Vec::new(),
+            timeline: Timeline::default(),
+            shader_params: ShaderParams::new(),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Serialize(serde_json::Error),
+    /// Parsed, but a value is out of range
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{err}"),
+            SceneError::Parse(err) => write!(f, "invalid scene file: {err}"),
+            SceneError::Serialize(err) => write!(f, "could not serialize scene: {err}"),
+            SceneError::Invalid(msg) => write!(f, "invalid scene file: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+
+impl Scene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let text = fs::read_to_string(path).map_err(SceneError::Io)?;
+        let scene: Scene = serde_json::from_str(&text).map_err(SceneError::Parse)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Rejects values the renderers can't work with.
+    fn validate(&self) -> Result<(), SceneError> {
+        if self.detail < 1 {
+            return Err(SceneError::Invalid(format!("detail must be at least 1, got {}", self.detail)));
+        }
+        self.graph.validate().map_err(|err| SceneError::Invalid(err.to_string()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        let text = serde_json::to_string_pretty(self).map_err(SceneError::Serialize)?;
+        fs::write(path, text).map_err(SceneError::Io)
+    }
+
+    pub fn camera(&self) -> Camera {
+        let mut camera = Camera { pos: self.position, ..Default::default() };
+        match self.orientation {
+            Some(orientation) => camera.orientation = orientation,
+            None => camera.set_euler_angles(self.angle),
+        }
+        camera
+    }
+
+    /// Overwrites every parameter the timeline has keys for.
+    pub fn apply_sample(&mut self, sample: &TimelineSample) {
+        let mut camera = self.camera();
+        sample.apply_camera(&mut camera);
+        self.position = camera.pos;
+        if sample.orientation.is_some() {
+            let (x, y, z) = camera.orientation.euler_angles();
+            self.angle = (x.to_degrees(), y.to_degrees(), z.to_degrees());
+            self.orientation = Some(camera.orientation);
+        }
+        sample.apply_params(&mut self.exp, &mut self.num_iters, &mut self.detail, &mut self.palette);
+    }
+
+    pub fn render_settings(&self, width: u32, height: u32) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            exp: self.exp,
+            iterations: self.num_iters,
+            detail: self.detail,
+            palette: self.palette,
+            fractal: self.fractal,
+            fractal_params: self.fractal_params,
+            graph: self.graph.clone(),
+            lighting: self.lighting.clone(),
+            coloring: self.coloring.clone(),
+            orbit_traps: self.orbit_traps.clone(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_json(name: &str, json: &str) -> Result<Scene, SceneError> {
+        let path = std::env::temp_dir().join(format!("meshview_scene_{}_{name}.json", std::process::id()));
+        fs::write(&path, json).unwrap();
+        let scene = Scene::load(&path);
+        fs::remove_file(&path).unwrap();
+        scene
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_defaults() {
+        let scene = load_json("defaults", r#"{"exp": 6.0}"#).unwrap();
+        assert_eq!(scene.exp, 6.0);
+        assert_eq!(scene.detail, 1);
+        assert_eq!(scene.graph, SdfNode::Fractal);
+    }
+
+    #[test]
+    fn graph_with_zero_smoothness_is_rejected() {
+        let json = r#"{"graph": {"SmoothUnion": {"a": "Fractal", "b": {"Sphere": {"radius": 1.0}}, "k": 0.0}}}"#;
+        assert!(matches!(load_json("smoothness", json), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn detail_below_one_is_rejected() {
+        for detail in [0, -5] {
+            let result = load_json("detail", &format!(r#"{{"detail": {detail}}}"#));
+            assert!(matches!(result, Err(SceneError::Invalid(_))), "{detail}");
+        }
+    }
+
+    #[test]
+    fn applying_a_sample_only_touches_keyed_parameters() {
+        let mut scene = Scene { num_iters: 7, ..Scene::default() };
+        let rotation = UnitQuaternion::from_euler_angles(0.0, 30.0_f32.to_radians(), 0.0);
+        let sample = TimelineSample {
+            position: Some(Vector3::new(1.0, 2.0, 3.0)),
+            orientation: Some(rotation),
+            exp: Some(4.0),
+            palette: [None, Some(Vector3::x()), None],
+            ..TimelineSample::default()
+        };
+        scene.apply_sample(&sample);
+
+        assert_eq!(scene.position, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(scene.orientation, Some(rotation));
+        assert!((scene.angle.1 - 30.0).abs() < 1.0e-3);
+        assert_eq!(scene.camera().orientation, rotation);
+        assert_eq!(scene.exp, 4.0);
+        assert_eq!(scene.num_iters, 7);
+        assert_eq!(scene.palette[0], DEFAULT_PALETTE[0]);
+        assert_eq!(scene.palette[1], Vector3::x());
+    }
+}
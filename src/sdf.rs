@@ -0,0 +1,609 @@
+use nalgebra::{Vector3, Vector4};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::orbit_trap::{PackedTraps, NO_TRAP};
+
+// CPU ports of the distance estimators in main.frag.glsl. These are kept in lockstep
+// with the shader so distances can be checked without a GL context.
+
+/// Which distance estimator the march loop uses. The discriminant is the value of `u_Fractal`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FractalType {
+    #[default]
+    Mandelbulb,
+    Mandelbox,
+    Menger,
+    Sierpinski,
+    QuaternionJulia,
+    Kleinian,
+    Apollonian,
+}
+
+impl FractalType {
+    pub const ALL: [FractalType; 7] = [
+        FractalType::Mandelbulb,
+        FractalType::Mandelbox,
+        FractalType::Menger,
+        FractalType::Sierpinski,
+        FractalType::QuaternionJulia,
+        FractalType::Kleinian,
+        FractalType::Apollonian,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FractalType::Mandelbulb => "Mandelbulb",
+            FractalType::Mandelbox => "Mandelbox",
+            FractalType::Menger => "Menger sponge",
+            FractalType::Sierpinski => "Sierpinski tetrahedron",
+            FractalType::QuaternionJulia => "Quaternion Julia",
+            FractalType::Kleinian => "Kleinian",
+            FractalType::Apollonian => "Apollonian",
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct MandelboxParams {
+    pub scale: f32,
+    pub min_radius: f32,
+    pub fixed_radius: f32,
+    #[serde(deserialize_with = "non_negative")]
+    pub folding_limit: f32,
+}
+
+impl Default for MandelboxParams {
+    fn default() -> Self {
+        Self { scale: -1.5, min_radius: 0.5, fixed_radius: 1.0, folding_limit: 1.0 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct MengerParams {
+    pub scale: f32,
+    pub offset: Vector3<f32>,
+}
+
+impl Default for MengerParams {
+    fn default() -> Self {
+        Self { scale: 3.0, offset: Vector3::new(1.0, 1.0, 1.0) }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct SierpinskiParams {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Default for SierpinskiParams {
+    fn default() -> Self {
+        Self { scale: 2.0, offset: 1.0 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct JuliaParams {
+    pub c: Vector4<f32>,
+    /// w coordinate of the 3D slice through the 4D set
+    pub slice: f32,
+}
+
+impl Default for JuliaParams {
+    fn default() -> Self {
+        Self { c: Vector4::new(-0.2, 0.6, 0.2, 0.2), slice: 0.0 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct KleinianParams {
+    #[serde(deserialize_with = "non_negative_vec3")]
+    pub box_size: Vector3<f32>,
+    pub sphere_size: f32,
+    pub thickness: f32,
+    /// The set tiles all of space, so it is cut down to a ball of this radius
+    pub bounding_radius: f32,
+}
+
+impl Default for KleinianParams {
+    fn default() -> Self {
+        Self { box_size: Vector3::new(0.92436, 0.90756, 0.92436), sphere_size: 1.0, thickness: 0.92784, bounding_radius: 1.5 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct ApollonianParams {
+    pub scale: f32,
+    /// The packing tiles all of space, so it is cut down to a ball of this radius
+    pub bounding_radius: f32,
+}
+
+impl Default for ApollonianParams {
+    fn default() -> Self {
+        Self { scale: 1.3, bounding_radius: 1.5 }
+    }
+}
+
+
+// Fold limits are used as ±limit, so negative values in hand-written files are clamped to
+// zero. The estimators treat negative values set in code the same way
+fn non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    f32::deserialize(deserializer).map(|x| x.max(0.0))
+}
+
+fn non_negative_vec3<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vector3<f32>, D::Error> {
+    Vector3::<f32>::deserialize(deserializer).map(|v| v.map(|x| x.max(0.0)))
+}
+
+
+/// Per-family parameters. Only the set for the selected `FractalType` is used; the
+/// others are kept so switching back and forth doesn't lose tweaks.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct FractalParams {
+    pub mandelbox: MandelboxParams,
+    pub menger: MengerParams,
+    pub sierpinski: SierpinskiParams,
+    pub julia: JuliaParams,
+    pub kleinian: KleinianParams,
+    pub apollonian: ApollonianParams,
+}
+
+impl FractalParams {
+    /// Parameters of `kind` packed the way the shader reads `u_FractalParams[2]`.
+    pub fn packed(&self, kind: FractalType) -> [f32; 8] {
+        match kind {
+            FractalType::Mandelbulb => [0.0; 8],
+            FractalType::Mandelbox => {
+                let p = &self.mandelbox;
+                [p.scale, p.min_radius, p.fixed_radius, p.folding_limit, 0.0, 0.0, 0.0, 0.0]
+            }
+            FractalType::Menger => {
+                let p = &self.menger;
+                [p.scale, p.offset.x, p.offset.y, p.offset.z, 0.0, 0.0, 0.0, 0.0]
+            }
+            FractalType::Sierpinski => {
+                let p = &self.sierpinski;
+                [p.scale, p.offset, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+            }
+            FractalType::QuaternionJulia => {
+                let p = &self.julia;
+                [p.c.x, p.c.y, p.c.z, p.c.w, p.slice, 0.0, 0.0, 0.0]
+            }
+            FractalType::Kleinian => {
+                let p = &self.kleinian;
+                [p.box_size.x, p.box_size.y, p.box_size.z, p.sphere_size, p.thickness, p.bounding_radius, 0.0, 0.0]
+            }
+            FractalType::Apollonian => {
+                let p = &self.apollonian;
+                [p.scale, p.bounding_radius, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+            }
+        }
+    }
+}
+
+
+/// What the orbit did at a point, for coloring. Mirrors `Orbit` in main.frag.glsl.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Orbit {
+    /// Closest the orbit came to each channel's traps, see `PackedTraps::evaluate`
+    pub trap: Vector4<f32>,
+    /// Iteration the orbit escaped at, or the iteration limit if it never did. The
+    /// families without a bailout always run to the limit
+    pub iterations: u32,
+}
+
+impl Orbit {
+    fn start(iterations: u32) -> Self {
+        Self { trap: Vector4::repeat(NO_TRAP), iterations }
+    }
+
+    fn record(&mut self, traps: &PackedTraps, z: Vector3<f32>) {
+        self.trap = self.trap.inf(&traps.evaluate(z));
+    }
+}
+
+
+/// Everything needed to evaluate the selected fractal at a point.
+#[derive(Clone, Copy, Debug)]
+pub struct DistanceEstimator {
+    pub kind: FractalType,
+    /// Mandelbulb exponent (`u_Exp`)
+    pub power: f32,
+    pub iterations: u32,
+    pub params: FractalParams,
+    pub traps: PackedTraps,
+}
+
+impl DistanceEstimator {
+    /// Returns `(distance, orbit)`, matching `fractal()` in the shader.
+    pub fn distance(&self, p: Vector3<f32>) -> (f32, Orbit) {
+        let iters = self.iterations;
+        let params = &self.params;
+        let traps = &self.traps;
+
+        match self.kind {
+            FractalType::Mandelbulb => mandelbulb(p, self.power, iters, traps),
+            FractalType::Mandelbox => mandelbox(p, iters, &params.mandelbox, traps),
+            FractalType::Menger => menger(p, iters, &params.menger, traps),
+            FractalType::Sierpinski => sierpinski(p, iters, &params.sierpinski, traps),
+            FractalType::QuaternionJulia => quaternion_julia(p, iters, &params.julia, traps),
+            FractalType::Kleinian => kleinian(p, iters, &params.kleinian, traps),
+            FractalType::Apollonian => apollonian(p, iters, &params.apollonian, traps),
+        }
+    }
+
+    pub fn normal(&self, p: Vector3<f32>) -> Vector3<f32> {
+        normal(p, |q| self.distance(q).0)
+    }
+}
+
+
+/// Surface normal from a one-sided finite difference, matching `getNormal` in the shader.
+pub fn normal(p: Vector3<f32>, distance: impl Fn(Vector3<f32>) -> f32) -> Vector3<f32> {
+    let d = distance(p);
+    let e = 0.001;
+    let n = Vector3::new(
+        d - distance(p - Vector3::new(e, 0.0, 0.0)),
+        d - distance(p - Vector3::new(0.0, e, 0.0)),
+        d - distance(p - Vector3::new(0.0, 0.0, e)),
+    );
+
+    n.normalize()
+}
+
+
+pub fn sd_sphere(p: Vector3<f32>, s: f32) -> f32 {
+    p.norm() - s
+}
+
+pub fn sd_box(p: Vector3<f32>, b: Vector3<f32>) -> f32 {
+    let q = p.abs() - b;
+    q.sup(&Vector3::zeros()).norm() + q.x.max(q.y.max(q.z)).min(0.0)
+}
+
+/// Torus in the xz plane.
+pub fn sd_torus(p: Vector3<f32>, major_radius: f32, minor_radius: f32) -> f32 {
+    let q = nalgebra::Vector2::new(p.xz().norm() - major_radius, p.y);
+    q.norm() - minor_radius
+}
+
+pub fn sd_capsule(p: Vector3<f32>, a: Vector3<f32>, b: Vector3<f32>, r: f32) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0.0, 1.0);
+    (pa - ba * h).norm() - r
+}
+
+/// Plane through `normal * offset`. `normal` must be normalized.
+pub fn sd_plane(p: Vector3<f32>, normal: Vector3<f32>, offset: f32) -> f32 {
+    p.dot(&normal) - offset
+}
+
+pub fn smin(a: f32, b: f32, k: f32) -> f32 {
+    let r = (-a / k).exp2() + (-b / k).exp2();
+    -k * r.log2()
+}
+
+/// Mandelbulb distance estimate at `pos`. Returns `(distance, orbit)`, where the orbit
+/// holds the closest it came to each channel's `traps`, same as the shader.
+pub fn mandelbulb(pos: Vector3<f32>, power: f32, iterations: u32, traps: &PackedTraps) -> (f32, Orbit) {
+    let mut z = pos;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+
+    let mut orbit = Orbit::start(iterations);
+
+    for i in 0..iterations {
+        r = z.norm();
+        if r > 2.0 {
+            orbit.iterations = i;
+            break;
+        }
+
+        // Convert to polar coordinates
+        let mut theta = (z.z / r).acos();
+        let mut phi = z.y.atan2(z.x);
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+
+        // Scale and rotate
+        let zr = r.powf(power);
+        theta *= power;
+        phi *= power;
+
+        // Convert back to cartesian coordinates
+        z = zr * Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+        z += pos;
+
+        orbit.record(traps, z);
+    }
+
+    (0.5 * r.ln() * r / dr, orbit)
+}
+
+pub fn mandelbox(pos: Vector3<f32>, iterations: u32, params: &MandelboxParams, traps: &PackedTraps) -> (f32, Orbit) {
+    let min_r2 = params.min_radius * params.min_radius;
+    let fixed_r2 = params.fixed_radius * params.fixed_radius;
+    // f32::clamp panics if min > max; negative limits fold like zero, as when loading
+    let limit = params.folding_limit.max(0.0);
+
+    let mut z = pos;
+    let mut dr = 1.0;
+    let mut orbit = Orbit::start(iterations);
+
+    for _ in 0..iterations {
+        // box fold
+        z = z.map(|c| c.clamp(-limit, limit) * 2.0 - c);
+
+        // sphere fold
+        let r2 = z.norm_squared();
+        if r2 < min_r2 {
+            let t = fixed_r2 / min_r2;
+            z *= t;
+            dr *= t;
+        } else if r2 < fixed_r2 {
+            let t = fixed_r2 / r2;
+            z *= t;
+            dr *= t;
+        }
+
+        z = params.scale * z + pos;
+        dr = dr * params.scale.abs() + 1.0;
+
+        orbit.record(traps, z);
+    }
+
+    (z.norm() / dr.abs(), orbit)
+}
+
+pub fn menger(pos: Vector3<f32>, iterations: u32, params: &MengerParams, traps: &PackedTraps) -> (f32, Orbit) {
+    let scale = params.scale;
+    let offset = params.offset;
+
+    let mut z = pos;
+    let mut orbit = Orbit::start(iterations);
+
+    for _ in 0..iterations {
+        z = z.abs();
+        if z.x < z.y { z = Vector3::new(z.y, z.x, z.z); }
+        if z.x < z.z { z = Vector3::new(z.z, z.y, z.x); }
+        if z.y < z.z { z = Vector3::new(z.x, z.z, z.y); }
+
+        z = scale * z - offset * (scale - 1.0);
+        if z.z < -0.5 * offset.z * (scale - 1.0) {
+            z.z += offset.z * (scale - 1.0);
+        }
+
+        orbit.record(traps, z);
+    }
+
+    (sd_box(z, Vector3::new(1.0, 1.0, 1.0)) * scale.powi(-(iterations as i32)), orbit)
+}
+
+pub fn sierpinski(pos: Vector3<f32>, iterations: u32, params: &SierpinskiParams, traps: &PackedTraps) -> (f32, Orbit) {
+    let scale = params.scale;
+    let offset = Vector3::repeat(params.offset);
+
+    let mut z = pos;
+    let mut orbit = Orbit::start(iterations);
+
+    for _ in 0..iterations {
+        // fold across the tetrahedron's symmetry planes
+        if z.x + z.y < 0.0 { z = Vector3::new(-z.y, -z.x, z.z); }
+        if z.x + z.z < 0.0 { z = Vector3::new(-z.z, z.y, -z.x); }
+        if z.y + z.z < 0.0 { z = Vector3::new(z.x, -z.z, -z.y); }
+
+        z = z * scale - offset * (scale - 1.0);
+
+        orbit.record(traps, z);
+    }
+
+    (sd_tetrahedron(z) * scale.powi(-(iterations as i32)), orbit)
+}
+
+/// Regular tetrahedron with corners at (1,1,1), (-1,-1,1), (1,-1,-1) and (-1,1,-1).
+pub fn sd_tetrahedron(p: Vector3<f32>) -> f32 {
+    let d = (-p.x - p.y - p.z).max(p.x + p.y - p.z).max((-p.x + p.y + p.z).max(p.x - p.y + p.z));
+    (d - 1.0) / 3.0_f32.sqrt()
+}
+
+pub fn quaternion_julia(pos: Vector3<f32>, iterations: u32, params: &JuliaParams, traps: &PackedTraps) -> (f32, Orbit) {
+    let mut z = Vector4::new(pos.x, pos.y, pos.z, params.slice);
+    let mut md2 = 1.0;
+    let mut mz2 = z.norm_squared();
+    let mut orbit = Orbit::start(iterations);
+
+    for i in 0..iterations {
+        md2 *= 4.0 * mz2;
+        // z = z^2 + c
+        let yzw = Vector3::new(z.y, z.z, z.w);
+        let sq = 2.0 * z.x * yzw;
+        z = Vector4::new(z.x * z.x - yzw.norm_squared(), sq.x, sq.y, sq.z) + params.c;
+        mz2 = z.norm_squared();
+
+        orbit.record(traps, z.xyz());
+        if mz2 > 4.0 {
+            orbit.iterations = i + 1;
+            break;
+        }
+    }
+
+    (0.25 * (mz2 / md2).sqrt() * mz2.ln(), orbit)
+}
+
+pub fn kleinian(pos: Vector3<f32>, iterations: u32, params: &KleinianParams, traps: &PackedTraps) -> (f32, Orbit) {
+    // f32::clamp panics if min > max; negative sizes fold like zero, as when loading
+    let box_size = params.box_size.map(|b| b.max(0.0));
+    let mut p = pos;
+    let mut de_factor = 1.0;
+    let mut orbit = Orbit::start(iterations);
+
+    for _ in 0..iterations {
+        p = 2.0 * p.zip_map(&box_size, |c, b| c.clamp(-b, b)) - p;
+        let k = (params.sphere_size / p.norm_squared()).max(1.0);
+        p *= k;
+        de_factor *= k;
+
+        orbit.record(traps, p);
+    }
+
+    let rxy = p.xy().norm();
+    let d = (rxy - params.thickness).max((rxy * p.z).abs() / p.norm()) / de_factor;
+    (d.max(sd_sphere(pos, params.bounding_radius)), orbit)
+}
+
+pub fn apollonian(pos: Vector3<f32>, iterations: u32, params: &ApollonianParams, traps: &PackedTraps) -> (f32, Orbit) {
+    let mut p = pos;
+    let mut scale = 1.0;
+    let mut orbit = Orbit::start(iterations);
+
+    for _ in 0..iterations {
+        p = p.map(|c| -1.0 + 2.0 * (0.5 * c + 0.5).rem_euclid(1.0));
+        let r2 = p.norm_squared();
+        orbit.record(traps, p);
+
+        let k = params.scale / r2;
+        p *= k;
+        scale *= k;
+    }
+
+    let d = 0.25 * p.y.abs() / scale;
+    (d.max(sd_sphere(pos, params.bounding_radius)), orbit)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orbit_trap::OrbitTraps;
+
+    fn estimator(kind: FractalType) -> DistanceEstimator {
+        DistanceEstimator {
+            kind,
+            power: 8.0,
+            iterations: 12,
+            params: FractalParams::default(),
+            traps: OrbitTraps::default().packed(),
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1.0e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn primitives_are_exact() {
+        assert_close(sd_sphere(Vector3::new(0.0, 3.0, 0.0), 1.0), 2.0);
+        assert_close(sd_sphere(Vector3::zeros(), 1.0), -1.0);
+        assert_close(sd_box(Vector3::new(2.0, 0.0, 0.0), Vector3::repeat(1.0)), 1.0);
+        assert_close(sd_box(Vector3::new(2.0, 2.0, 1.0), Vector3::repeat(1.0)), 2.0_f32.sqrt());
+        assert_close(sd_box(Vector3::zeros(), Vector3::new(1.0, 0.5, 2.0)), -0.5);
+        assert_close(sd_torus(Vector3::new(1.0, 0.5, 0.0), 1.0, 0.25), 0.25);
+        assert_close(sd_capsule(Vector3::new(0.5, 1.0, 0.0), Vector3::zeros(), Vector3::x(), 0.5), 0.5);
+        assert_close(sd_capsule(Vector3::new(-1.0, 0.0, 0.0), Vector3::zeros(), Vector3::x(), 0.5), 0.5);
+        assert_close(sd_plane(Vector3::new(4.0, 3.0, 0.0), Vector3::y(), 1.0), 2.0);
+        assert_close(sd_tetrahedron(Vector3::new(1.0, 1.0, 1.0)), 0.0);
+        assert_close(sd_tetrahedron(Vector3::zeros()), -1.0 / 3.0_f32.sqrt());
+    }
+
+    #[test]
+    fn smin_blends_below_both_inputs() {
+        let blended = smin(1.0, 1.0, 0.5);
+        assert!(blended < 1.0);
+        // far apart the blend is just the smaller input
+        assert_close(smin(0.0, 50.0, 0.1), 0.0);
+    }
+
+    #[test]
+    fn normal_of_sphere_points_outwards() {
+        let p = Vector3::new(0.6, 0.0, 0.8);
+        let n = normal(p, |q| sd_sphere(q, 1.0));
+        assert!((n - p).norm() < 1.0e-2, "{n:?}");
+    }
+
+    #[test]
+    fn fractals_underestimate_the_distance_from_outside() {
+        // every family fits in a ball of radius 2 with the default parameters, so a point
+        // at distance 4 is positive and not further than the ball
+        for kind in FractalType::ALL {
+            let (d, _) = estimator(kind).distance(Vector3::new(0.0, 0.0, 4.0));
+            assert!(d.is_finite() && d > 0.0 && d <= 4.0, "{kind:?}: {d}");
+        }
+    }
+
+    #[test]
+    fn mandelbulb_contains_points_near_its_center() {
+        let (d, _) = estimator(FractalType::Mandelbulb).distance(Vector3::new(0.1, 0.1, 0.1));
+        assert!(d < 0.01, "{d}");
+    }
+
+    #[test]
+    fn mandelbulb_distance_shrinks_towards_the_surface() {
+        let de = estimator(FractalType::Mandelbulb);
+        let far = de.distance(Vector3::new(0.0, 0.0, 3.0)).0;
+        let near = de.distance(Vector3::new(0.0, 0.0, 1.5)).0;
+        assert!(near < far, "{near} >= {far}");
+    }
+
+    #[test]
+    fn orbit_trap_tracks_the_closest_approach() {
+        let (_, orbit) = estimator(FractalType::Mandelbulb).distance(Vector3::new(0.3, 0.2, 0.4));
+        assert!(orbit.trap.iter().all(|c| c.is_finite()));
+        assert!(orbit.trap.w < NO_TRAP);
+    }
+
+    #[test]
+    fn orbit_counts_iterations_until_escape() {
+        let mandelbulb = estimator(FractalType::Mandelbulb);
+        // outside the bailout radius from the start
+        assert_eq!(mandelbulb.distance(Vector3::new(0.0, 0.0, 4.0)).1.iterations, 0);
+        // inside the set it runs to the limit
+        assert_eq!(mandelbulb.distance(Vector3::new(0.1, 0.1, 0.1)).1.iterations, 12);
+        let (_, orbit) = mandelbulb.distance(Vector3::new(0.0, 0.0, 1.2));
+        assert!(orbit.iterations > 0 && orbit.iterations < 12, "{}", orbit.iterations);
+
+        let julia = estimator(FractalType::QuaternionJulia);
+        assert_eq!(julia.distance(Vector3::new(0.0, 0.0, 4.0)).1.iterations, 1);
+
+        // no bailout
+        assert_eq!(estimator(FractalType::Menger).distance(Vector3::new(0.0, 0.0, 4.0)).1.iterations, 12);
+    }
+
+    #[test]
+    fn negative_fold_limits_fold_like_zero() {
+        let p = Vector3::new(0.3, -0.4, 0.5);
+        let traps = OrbitTraps::default().packed();
+
+        let kleinian_params = KleinianParams::default();
+        let negative = KleinianParams { box_size: Vector3::new(-0.5, 0.9, -0.9), ..kleinian_params };
+        let zeroed = KleinianParams { box_size: Vector3::new(0.0, 0.9, 0.0), ..kleinian_params };
+        assert_eq!(kleinian(p, 8, &negative, &traps).0, kleinian(p, 8, &zeroed, &traps).0);
+
+        let mandelbox_params = MandelboxParams::default();
+        let negative = MandelboxParams { folding_limit: -1.0, ..mandelbox_params };
+        let zeroed = MandelboxParams { folding_limit: 0.0, ..mandelbox_params };
+        assert_eq!(mandelbox(p, 8, &negative, &traps).0, mandelbox(p, 8, &zeroed, &traps).0);
+    }
+
+    #[test]
+    fn negative_fold_limits_are_clamped_when_loading() {
+        let params: FractalParams = serde_json::from_str(r#"{"kleinian": {"box_size": [-0.5, 0.9, 0.9]}, "mandelbox": {"folding_limit": -1.0}}"#).unwrap();
+        assert_eq!(params.kleinian.box_size, Vector3::new(0.0, 0.9, 0.9));
+        assert_eq!(params.mandelbox.folding_limit, 0.0);
+    }
+
+    #[test]
+    fn packed_params_follow_the_shader_layout() {
+        let params = FractalParams::default();
+        assert_eq!(params.packed(FractalType::Mandelbulb), [0.0; 8]);
+        assert_eq!(params.packed(FractalType::Mandelbox)[..4], [-1.5, 0.5, 1.0, 1.0]);
+        assert_eq!(params.packed(FractalType::Kleinian)[5], params.kleinian.bounding_radius);
+    }
+}
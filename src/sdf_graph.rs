@@ -0,0 +1,401 @@
+use std::fmt::{self, Write};
+
+use nalgebra::{UnitQuaternion, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::sdf::{self, DistanceEstimator, Orbit};
+
+
+/// A node in the scene's distance field. The tree is evaluated directly on the CPU and
+/// compiled to the shader's `map()` function for the GPU.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub enum SdfNode {
+    /// The fractal picked in the UI (`u_Fractal`)
+    #[default]
+    Fractal,
+    Sphere { radius: f32 },
+    Box { half_extents: Vector3<f32> },
+    /// Torus lying in the xz plane
+    Torus { major_radius: f32, minor_radius: f32 },
+    Capsule { a: Vector3<f32>, b: Vector3<f32>, radius: f32 },
+    Plane { normal: Vector3<f32>, offset: f32 },
+    Union(Box<SdfNode>, Box<SdfNode>),
+    Intersection(Box<SdfNode>, Box<SdfNode>),
+    /// First child minus the second
+    Subtraction(Box<SdfNode>, Box<SdfNode>),
+    SmoothUnion { a: Box<SdfNode>, b: Box<SdfNode>, k: f32 },
+    /// Places the child in the parent's space. Scale is uniform so distances stay exact.
+    Transform {
+        translation: Vector3<f32>,
+        rotation: UnitQuaternion<f32>,
+        scale: f32,
+        child: Box<SdfNode>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphError {
+    /// A parameter is NaN or infinite
+    NotFinite { node: &'static str, field: &'static str },
+    /// A parameter that has to be above zero isn't
+    NotPositive { node: &'static str, field: &'static str, value: f32 },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::NotFinite { node, field } => write!(f, "{node} {field} is not a finite number"),
+            GraphError::NotPositive { node, field, value } => write!(f, "{node} {field} must be above zero, got {value}"),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+// orbit of plain geometry: full brightness, mid gray in the trap channel mode, and the
+// bottom of the gradient in the iteration mode
+const PRIMITIVE_ORBIT: Orbit = Orbit { trap: Vector4::new(0.5, 0.5, 0.5, 1.0), iterations: 0 };
+
+/// Where the generated code goes in main.frag.glsl.
+pub const MAP_MARKER: &str = "// @map";
+
+
+impl SdfNode {
+    pub fn union(a: SdfNode, b: SdfNode) -> Self {
+        SdfNode::Union(Box::new(a), Box::new(b))
+    }
+
+    pub fn intersection(a: SdfNode, b: SdfNode) -> Self {
+        SdfNode::Intersection(Box::new(a), Box::new(b))
+    }
+
+    pub fn subtraction(a: SdfNode, b: SdfNode) -> Self {
+        SdfNode::Subtraction(Box::new(a), Box::new(b))
+    }
+
+    pub fn smooth_union(a: SdfNode, b: SdfNode, k: f32) -> Self {
+        SdfNode::SmoothUnion { a: Box::new(a), b: Box::new(b), k }
+    }
+
+    pub fn translated(self, translation: Vector3<f32>) -> Self {
+        SdfNode::Transform { translation, rotation: UnitQuaternion::identity(), scale: 1.0, child: Box::new(self) }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SdfNode::Fractal => "Fractal",
+            SdfNode::Sphere { .. } => "Sphere",
+            SdfNode::Box { .. } => "Box",
+            SdfNode::Torus { .. } => "Torus",
+            SdfNode::Capsule { .. } => "Capsule",
+            SdfNode::Plane { .. } => "Plane",
+            SdfNode::Union(..) => "Union",
+            SdfNode::Intersection(..) => "Intersection",
+            SdfNode::Subtraction(..) => "Subtraction",
+            SdfNode::SmoothUnion { .. } => "Smooth union",
+            SdfNode::Transform { .. } => "Transform",
+        }
+    }
+
+    /// Checks that every parameter is finite and that smoothness, scale and plane normals
+    /// are nonzero. Anything else gives NaN distances, or GLSL literals like `NaN` that
+    /// don't compile.
+    pub fn validate(&self) -> Result<(), GraphError> {
+        let node = self.name();
+        let finite = |field, values: &[f32]| {
+            if values.iter().all(|x| x.is_finite()) { Ok(()) } else { Err(GraphError::NotFinite { node, field }) }
+        };
+        let positive = |field, value: f32| {
+            if value > 0.0 { Ok(()) } else { Err(GraphError::NotPositive { node, field, value }) }
+        };
+
+        match self {
+            SdfNode::Fractal => Ok(()),
+            SdfNode::Sphere { radius } => finite("radius", &[*radius]),
+            SdfNode::Box { half_extents } => finite("half extents", half_extents.as_slice()),
+            SdfNode::Torus { major_radius, minor_radius } => finite("radii", &[*major_radius, *minor_radius]),
+            SdfNode::Capsule { a, b, radius } => {
+                finite("end points", a.as_slice())?;
+                finite("end points", b.as_slice())?;
+                finite("radius", &[*radius])
+            }
+            SdfNode::Plane { normal, offset } => {
+                finite("normal", normal.as_slice())?;
+                finite("offset", &[*offset])?;
+                positive("normal length", normal.norm())
+            }
+            SdfNode::Union(a, b) | SdfNode::Intersection(a, b) | SdfNode::Subtraction(a, b) => {
+                a.validate()?;
+                b.validate()
+            }
+            SdfNode::SmoothUnion { a, b, k } => {
+                finite("smoothness", &[*k])?;
+                positive("smoothness", *k)?;
+                a.validate()?;
+                b.validate()
+            }
+            SdfNode::Transform { translation, rotation, scale, child } => {
+                finite("translation", translation.as_slice())?;
+                finite("rotation", rotation.coords.as_slice())?;
+                finite("scale", &[*scale])?;
+                positive("scale", *scale)?;
+                child.validate()
+            }
+        }
+    }
+
+    /// Returns `(distance, orbit)` at `p`. The orbit follows whichever child decides the
+    /// distance.
+    pub fn distance(&self, p: Vector3<f32>, fractal: &DistanceEstimator) -> (f32, Orbit) {
+        let primitive = PRIMITIVE_ORBIT;
+        match self {
+            SdfNode::Fractal => fractal.distance(p),
+            SdfNode::Sphere { radius } => (sdf::sd_sphere(p, *radius), primitive),
+            SdfNode::Box { half_extents } => (sdf::sd_box(p, *half_extents), primitive),
+            SdfNode::Torus { major_radius, minor_radius } => (sdf::sd_torus(p, *major_radius, *minor_radius), primitive),
+            SdfNode::Capsule { a, b, radius } => (sdf::sd_capsule(p, *a, *b, *radius), primitive),
+            SdfNode::Plane { normal, offset } => (sdf::sd_plane(p, normal.normalize(), *offset), primitive),
+            SdfNode::Union(a, b) => {
+                let (a, b) = (a.distance(p, fractal), b.distance(p, fractal));
+                if a.0 < b.0 { a } else { b }
+            }
+            SdfNode::Intersection(a, b) => {
+                let (a, b) = (a.distance(p, fractal), b.distance(p, fractal));
+                if a.0 > b.0 { a } else { b }
+            }
+            SdfNode::Subtraction(a, b) => {
+                let (a, b) = (a.distance(p, fractal), b.distance(p, fractal));
+                if a.0 > -b.0 { a } else { (-b.0, b.1) }
+            }
+            SdfNode::SmoothUnion { a, b, k } => {
+                let (a, b) = (a.distance(p, fractal), b.distance(p, fractal));
+                (sdf::smin(a.0, b.0, *k), if a.0 < b.0 { a.1 } else { b.1 })
+            }
+            SdfNode::Transform { translation, rotation, scale, child } => {
+                let local = rotation.inverse() * (p - translation) / *scale;
+                let (d, orbit) = child.distance(local, fractal);
+                (d * scale, orbit)
+            }
+        }
+    }
+
+    /// GLSL source for `float map(vec3 p, out Orbit orbit)`. Fails if the graph
+    /// doesn't pass `validate`.
+    pub fn to_glsl(&self) -> Result<String, GraphError> {
+        self.validate()?;
+
+        let mut body = String::new();
+        let mut next_id = 0;
+        let (d, o) = self.emit(&mut body, "p", &mut next_id);
+
+        Ok(format!("float map(vec3 p, out Orbit orbit) {{\n{body}    orbit = {o};\n    return {d};\n}}\n"))
+    }
+
+    /// Writes statements for this node into `out`, reading the position from `p`. Returns
+    /// the names of the variables holding the distance and orbit.
+    fn emit(&self, out: &mut String, p: &str, next_id: &mut u32) -> (String, String) {
+        let id = *next_id;
+        *next_id += 1;
+        let (d, o) = (format!("d{id}"), format!("o{id}"));
+
+        let primitive = |out: &mut String, expr: String| {
+            writeln!(out, "    float {d} = {expr};\n    Orbit {o} = Orbit({}, {});", glsl_vec4(&PRIMITIVE_ORBIT.trap), PRIMITIVE_ORBIT.iterations).unwrap();
+        };
+
+        match self {
+            SdfNode::Fractal => {
+                writeln!(out, "    Orbit {o};\n    float {d} = fractal({p}, {o});").unwrap();
+            }
+            SdfNode::Sphere { radius } => primitive(out, format!("sdSphere({p}, {})", glsl_float(*radius))),
+            SdfNode::Box { half_extents } => primitive(out, format!("sdBox({p}, {})", glsl_vec3(half_extents))),
+            SdfNode::Torus { major_radius, minor_radius } => {
+                primitive(out, format!("sdTorus({p}, {}, {})", glsl_float(*major_radius), glsl_float(*minor_radius)))
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                primitive(out, format!("sdCapsule({p}, {}, {}, {})", glsl_vec3(a), glsl_vec3(b), glsl_float(*radius)))
+            }
+            SdfNode::Plane { normal, offset } => {
+                primitive(out, format!("sdPlane({p}, {}, {})", glsl_vec3(&normal.normalize()), glsl_float(*offset)))
+            }
+            SdfNode::Union(a, b) => {
+                let ((da, oa), (db, ob)) = (a.emit(out, p, next_id), b.emit(out, p, next_id));
+                writeln!(out, "    float {d} = min({da}, {db});\n    Orbit {o} = {da} < {db} ? {oa} : {ob};").unwrap();
+            }
+            SdfNode::Intersection(a, b) => {
+                let ((da, oa), (db, ob)) = (a.emit(out, p, next_id), b.emit(out, p, next_id));
+                writeln!(out, "    float {d} = max({da}, {db});\n    Orbit {o} = {da} > {db} ? {oa} : {ob};").unwrap();
+            }
+            SdfNode::Subtraction(a, b) => {
+                let ((da, oa), (db, ob)) = (a.emit(out, p, next_id), b.emit(out, p, next_id));
+                writeln!(out, "    float {d} = max({da}, -{db});\n    Orbit {o} = {da} > -{db} ? {oa} : {ob};").unwrap();
+            }
+            SdfNode::SmoothUnion { a, b, k } => {
+                let ((da, oa), (db, ob)) = (a.emit(out, p, next_id), b.emit(out, p, next_id));
+                writeln!(out, "    float {d} = smin({da}, {db}, {});\n    Orbit {o} = {da} < {db} ? {oa} : {ob};", glsl_float(*k)).unwrap();
+            }
+            SdfNode::Transform { translation, rotation, scale, child } => {
+                let inv = rotation.inverse().to_rotation_matrix();
+                let m = inv.matrix();
+                // GLSL mat3 constructors are column major
+                let local = format!("p{id}");
+                writeln!(
+                    out,
+                    "    vec3 {local} = mat3({}, {}, {}, {}, {}, {}, {}, {}, {}) * ({p} - {}) / {};",
+                    glsl_float(m[(0, 0)]), glsl_float(m[(1, 0)]), glsl_float(m[(2, 0)]),
+                    glsl_float(m[(0, 1)]), glsl_float(m[(1, 1)]), glsl_float(m[(2, 1)]),
+                    glsl_float(m[(0, 2)]), glsl_float(m[(1, 2)]), glsl_float(m[(2, 2)]),
+                    glsl_vec3(translation), glsl_float(*scale)
+                ).unwrap();
+
+                let (dc, oc) = child.emit(out, &local, next_id);
+                writeln!(out, "    float {d} = {dc} * {};\n    Orbit {o} = {oc};", glsl_float(*scale)).unwrap();
+            }
+        }
+
+        (d, o)
+    }
+}
+
+
+/// Replaces the `// @map` marker in the fragment shader with the graph's `map()`.
+pub fn inject_map(frag_source: &str, graph: &SdfNode) -> Result<String, GraphError> {
+    Ok(frag_source.replacen(MAP_MARKER, &graph.to_glsl()?, 1))
+}
+
+// Debug formatting always keeps a decimal point or exponent, so the literal stays a float
+fn glsl_float(x: f32) -> String {
+    format!("{x:?}")
+}
+
+fn glsl_vec3(v: &Vector3<f32>) -> String {
+    format!("vec3({}, {}, {})", glsl_float(v.x), glsl_float(v.y), glsl_float(v.z))
+}
+
+fn glsl_vec4(v: &Vector4<f32>) -> String {
+    format!("vec4({}, {}, {}, {})", glsl_float(v.x), glsl_float(v.y), glsl_float(v.z), glsl_float(v.w))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Matrix3;
+
+    use super::*;
+    use crate::{orbit_trap::OrbitTraps, sdf::{FractalParams, FractalType}};
+
+    fn estimator() -> DistanceEstimator {
+        DistanceEstimator {
+            kind: FractalType::Mandelbulb,
+            power: 8.0,
+            iterations: 12,
+            params: FractalParams::default(),
+            traps: OrbitTraps::default().packed(),
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1.0e-5, "{a} != {b}");
+    }
+
+    fn node_count(node: &SdfNode) -> usize {
+        1 + match node {
+            SdfNode::Union(a, b) | SdfNode::Intersection(a, b) | SdfNode::Subtraction(a, b) => node_count(a) + node_count(b),
+            SdfNode::SmoothUnion { a, b, .. } => node_count(a) + node_count(b),
+            SdfNode::Transform { child, .. } => node_count(child),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn fractal_alone_calls_the_fractal() {
+        assert_eq!(
+            SdfNode::Fractal.to_glsl().unwrap(),
+            "float map(vec3 p, out Orbit orbit) {\n    Orbit o0;\n    float d0 = fractal(p, o0);\n    orbit = o0;\n    return d0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn csg_matches_its_children() {
+        let sphere = SdfNode::Sphere { radius: 1.0 };
+        let cube = SdfNode::Box { half_extents: Vector3::repeat(0.5) };
+        let p = Vector3::new(0.0, 0.0, 0.75);
+        let de = estimator();
+
+        assert_close(SdfNode::union(sphere.clone(), cube.clone()).distance(p, &de).0, -0.25);
+        assert_close(SdfNode::intersection(sphere.clone(), cube.clone()).distance(p, &de).0, 0.25);
+        assert_close(SdfNode::subtraction(sphere.clone(), cube.clone()).distance(p, &de).0, -0.25);
+        assert!(SdfNode::smooth_union(sphere, cube, 0.2).distance(p, &de).0 < -0.25);
+    }
+
+    #[test]
+    fn subtraction_keeps_the_cut_orbit() {
+        let graph = SdfNode::subtraction(SdfNode::Fractal, SdfNode::Sphere { radius: 5.0 });
+        let (d, orbit) = graph.distance(Vector3::zeros(), &estimator());
+        assert_close(d, 5.0);
+        assert_eq!(orbit, PRIMITIVE_ORBIT);
+    }
+
+    #[test]
+    fn transform_moves_and_scales_the_child() {
+        let graph = SdfNode::Transform {
+            translation: Vector3::new(2.0, 0.0, 0.0),
+            rotation: UnitQuaternion::identity(),
+            scale: 2.0,
+            child: Box::new(SdfNode::Sphere { radius: 1.0 }),
+        };
+        // a sphere of radius 2 around (2, 0, 0)
+        assert_close(graph.distance(Vector3::new(2.0, 5.0, 0.0), &estimator()).0, 3.0);
+    }
+
+    #[test]
+    fn every_node_gets_one_distance_variable() {
+        let graph = SdfNode::smooth_union(
+            SdfNode::union(SdfNode::Fractal, SdfNode::Torus { major_radius: 1.0, minor_radius: 0.2 }),
+            SdfNode::subtraction(
+                SdfNode::Capsule { a: Vector3::zeros(), b: Vector3::y(), radius: 0.1 }.translated(Vector3::x()),
+                SdfNode::Plane { normal: Vector3::y(), offset: 0.0 },
+            ),
+            0.3,
+        );
+        let glsl = graph.to_glsl().unwrap();
+        assert_eq!(glsl.matches("    float d").count(), node_count(&graph));
+        for call in ["fractal(p, ", "sdTorus(p, 1.0, 0.2)", "sdCapsule(p5, ", "sdPlane(p, vec3(0.0, 1.0, 0.0), 0.0)", "smin(d1, d4, 0.3)"] {
+            assert!(glsl.contains(call), "{call} missing from\n{glsl}");
+        }
+    }
+
+    #[test]
+    fn transform_matrix_is_column_major() {
+        let rotation = UnitQuaternion::from_euler_angles(0.3, -1.1, 0.7);
+        let graph = SdfNode::Transform { translation: Vector3::zeros(), rotation, scale: 1.0, child: Box::new(SdfNode::Fractal) };
+        let glsl = graph.to_glsl().unwrap();
+
+        let args = glsl.split("mat3(").nth(1).and_then(|rest| rest.split(')').next()).unwrap();
+        let values: Vec<f32> = args.split(", ").map(|v| v.parse().unwrap()).collect();
+        let m = Matrix3::from_column_slice(&values);
+
+        let p = Vector3::new(0.2, -0.5, 0.9);
+        assert!((m * p - rotation.inverse() * p).norm() < 1.0e-5);
+    }
+
+    #[test]
+    fn invalid_parameters_never_reach_glsl() {
+        let sphere = SdfNode::Sphere { radius: 1.0 };
+        for k in [0.0, -0.5, f32::NAN] {
+            let graph = SdfNode::smooth_union(SdfNode::Fractal, sphere.clone(), k);
+            assert!(graph.to_glsl().is_err(), "{k}");
+        }
+        let graph = SdfNode::union(SdfNode::Fractal, SdfNode::Sphere { radius: f32::INFINITY });
+        assert_eq!(graph.to_glsl(), Err(GraphError::NotFinite { node: "Sphere", field: "radius" }));
+        let graph = SdfNode::Plane { normal: Vector3::zeros(), offset: 0.0 };
+        assert!(graph.to_glsl().is_err());
+    }
+
+    #[test]
+    fn inject_map_replaces_the_marker() {
+        let source = "void main() {}\n// @map\n";
+        let injected = inject_map(source, &SdfNode::Fractal).unwrap();
+        assert!(!injected.contains(MAP_MARKER));
+        assert!(injected.starts_with("void main() {}\nfloat map(vec3 p"));
+    }
+}
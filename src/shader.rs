@@ -1,115 +1,139 @@
 // pub mod Shader {
     use eframe::glow::{self, Context};
 
-    use crate::{camera::Camera, mesh::Mesh};
+    use meshview::camera::Camera;
+
+    use crate::{error::GlError, mesh::Mesh, uniforms::UniformRegistry};
 
     
     pub struct ShaderProgram {
         pub program : glow::Program,
+        pub uniforms: UniformRegistry,
         _vert_shader: glow::Shader,
         _frag_shader: glow::Shader
     }
 
 
     impl ShaderProgram {
-        pub fn new(gl: &glow::Context, vs_path: &str, fs_path: &str) -> Self {
+        pub fn new(gl: &glow::Context, vertex_shader_source: &str, fragment_shader_source: &str) -> Result<Self, GlError> {
             use glow::HasContext as _;
 
             unsafe {
-                let program = gl.create_program().expect("Cannot create program");
-
-                let (vertex_shader_source, fragment_shader_source) = (
-                    std::fs::read_to_string(vs_path).unwrap(),
-                    std::fs::read_to_string(fs_path).unwrap(),
-                );
+                let program = gl.create_program().map_err(GlError::resource("shader program"))?;
 
                 let shader_sources = [
-                    (glow::VERTEX_SHADER, vertex_shader_source),
-                    (glow::FRAGMENT_SHADER, fragment_shader_source),
+                    ("vertex", glow::VERTEX_SHADER, vertex_shader_source),
+                    ("fragment", glow::FRAGMENT_SHADER, fragment_shader_source),
                 ];
 
-                let shaders: Vec<_> = shader_sources
-                .iter()
-                .map(|(shader_type, shader_source)| {
-                    let shader = gl
-                        .create_shader(*shader_type)
-                        .expect("Cannot create shader");
-                    gl.shader_source(shader, &format!("{shader_source}"));
+                let mut shaders = Vec::with_capacity(shader_sources.len());
+                for (stage, shader_type, shader_source) in shader_sources {
+                    let shader = match gl.create_shader(shader_type) {
+                        Ok(shader) => shader,
+                        Err(message) => {
+                            for shader in shaders {
+                                gl.delete_shader(shader);
+                            }
+                            gl.delete_program(program);
+                            return Err(GlError::Resource { object: "shader", message });
+                        }
+                    };
+                    gl.shader_source(shader, shader_source);
                     gl.compile_shader(shader);
-                    assert!(
-                        gl.get_shader_compile_status(shader),
-                        "Failed to compile {shader_type}: {}",
-                        gl.get_shader_info_log(shader)
-                    );
+                    if !gl.get_shader_compile_status(shader) {
+                        let log = gl.get_shader_info_log(shader);
+                        gl.delete_shader(shader);
+                        for shader in shaders {
+                            gl.delete_shader(shader);
+                        }
+                        gl.delete_program(program);
+                        return Err(GlError::Compile { stage, log });
+                    }
                     gl.attach_shader(program, shader);
-                    shader
-                })
-                .collect();
+                    shaders.push(shader);
+                }
 
 
-                // assert status of the shader
                 gl.link_program(program);
-                assert!(
-                    gl.get_program_link_status(program),
-                    "{}",
-                    gl.get_program_info_log(program)
-                );
+                let linked = gl.get_program_link_status(program);
 
                 for shader in shaders.iter() {
                     gl.detach_shader(program, *shader);
                     gl.delete_shader(*shader);
                 }
 
-                Self {
+                if !linked {
+                    let log = gl.get_program_info_log(program);
+                    gl.delete_program(program);
+                    return Err(GlError::Link { log });
+                }
+
+                Ok(Self {
                     program,
+                    uniforms: UniformRegistry::new(gl, program, &[vertex_shader_source, fragment_shader_source]),
                     _vert_shader: shaders[0],
                     _frag_shader: shaders[1]
-                }
+                })
             }
         }
 
 
-        pub fn _destroy(&self, gl: &glow::Context) {
+        pub fn destroy(&self, gl: &glow::Context) {
             use glow::HasContext as _;
             unsafe {
                 gl.delete_program(self.program);
             }
         }
 
-        pub fn paint<F: FnOnce(&Context, glow::Program)>(&self, gl: &glow::Context, mesh: &Mesh, camera: &Camera, set_uniforms: F) 
+        pub fn paint<F: FnOnce(&Context, &UniformRegistry)>(&self, gl: &glow::Context, mesh: &Mesh, camera: &Camera, set_uniforms: F) 
         {
+            self.draw(gl, mesh, |gl, uniforms| {
+                let view_proj = camera.get_proj_view_mat();
+                uniforms.set(gl, "u_ViewProj", view_proj);
+                uniforms.set(gl, "u_InvViewProj", view_proj.try_inverse().unwrap());
+                uniforms.set(gl, "aspectRatio", camera.aspect_ratio);
+                uniforms.set(gl, "u_CamPos", camera.pos);
+
+                set_uniforms(gl, uniforms);
+            });
+        }
+
+        /// Draws `mesh` with this program and only the uniforms `set_uniforms` sets, for
+        /// programs that don't take a camera. The depth buffer is replaced by whatever the
+        /// fragment shader writes, which for the fullscreen passes is the fractal's depth.
+        pub fn draw<F: FnOnce(&Context, &UniformRegistry)>(&self, gl: &glow::Context, mesh: &Mesh, set_uniforms: F) {
             use glow::HasContext as _;
 
             unsafe {
-                
+                gl.depth_mask(true);
                 gl.clear(glow::DEPTH_BUFFER_BIT);
-                gl.depth_func(glow::LESS);
+                // depth is only written with the test on
+                gl.depth_func(glow::ALWAYS);
                 gl.enable(glow::DEPTH_TEST);
+            }
+            self.draw_elements(gl, mesh, set_uniforms);
+        }
 
-                gl.use_program(Some(self.program));
-
-                gl.uniform_matrix_4_f32_slice(
-                    gl.get_uniform_location(self.program, "u_ViewProj").as_ref(),
-                    false, 
-                    camera.get_proj_view_mat().as_slice()
-                );
+        /// Like `draw`, but keeps the depth buffer and tests against it, for geometry
+        /// composited into the scene.
+        pub fn draw_over<F: FnOnce(&Context, &UniformRegistry)>(&self, gl: &glow::Context, mesh: &Mesh, set_uniforms: F) {
+            use glow::HasContext as _;
 
-                gl.uniform_matrix_4_f32_slice(
-                    gl.get_uniform_location(self.program, "u_InvViewProj").as_ref(),
-                    false, 
-                    camera.get_proj_view_mat().try_inverse().unwrap().as_slice()
-                );
+            unsafe {
+                gl.depth_mask(true);
+                gl.depth_func(glow::LESS);
+                gl.enable(glow::DEPTH_TEST);
+            }
+            self.draw_elements(gl, mesh, set_uniforms);
+        }
 
-                gl.uniform_1_f32(
-                    gl.get_uniform_location(self.program, "aspectRatio").as_ref(),
-                    camera.aspect_ratio);
+        fn draw_elements<F: FnOnce(&Context, &UniformRegistry)>(&self, gl: &glow::Context, mesh: &Mesh, set_uniforms: F) {
+            use glow::HasContext as _;
 
-                gl.uniform_3_f32(
-                    gl.get_uniform_location(self.program, "u_CamPos").as_ref(), 
-                    camera.pos.x, camera.pos.y, camera.pos.z
-                );
+            unsafe {
+                gl.use_program(Some(self.program));
 
-                set_uniforms(gl, self.program);
+                set_uniforms(gl, &self.uniforms);
 
                 gl.bind_vertex_array(Some(mesh.vertex_array));
                 gl.draw_elements(if mesh.wireframe {glow::LINES} else {glow::TRIANGLES}, mesh.index_buffer_size as i32, glow::UNSIGNED_INT, 0);
@@ -0,0 +1,34 @@
+use std::{collections::BTreeMap, fmt};
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+
+/// The value of a uniform that is edited through a control generated from the shader
+/// source, rather than through a dedicated field.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ParamValue {
+    Float(f32),
+    Int(i32),
+    UInt(u32),
+    Bool(bool),
+    Vec3(Vector3<f32>),
+}
+
+
+impl fmt::Display for ParamValue {
+    /// Vectors are written space separated, so the text needs no quoting in a CSV file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Float(value) => write!(f, "{value}"),
+            ParamValue::Int(value) => write!(f, "{value}"),
+            ParamValue::UInt(value) => write!(f, "{value}"),
+            ParamValue::Bool(value) => write!(f, "{value}"),
+            ParamValue::Vec3(value) => write!(f, "{} {} {}", value.x, value.y, value.z),
+        }
+    }
+}
+
+
+/// Uniform name to value.
+pub type ShaderParams = BTreeMap<String, ParamValue>;
@@ -0,0 +1,244 @@
+use std::{path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+use eframe::egui::{self, Color32, RichText};
+use meshview::sdf_graph::MAP_MARKER;
+
+use crate::error::GlError;
+
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const VERT_FILE: &str = "main.vert.glsl";
+const FRAG_FILE: &str = "main.frag.glsl";
+
+
+/// Where the shader sources come from. The release build carries its own copy; pointing
+/// at a directory instead reads the files from disk and reloads them when they change.
+pub enum ShaderSource {
+    Embedded,
+    Dir(PathBuf),
+}
+
+
+impl ShaderSource {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        dir.map_or(Self::Embedded, Self::Dir)
+    }
+
+    /// Returns the vertex and fragment sources, the latter still containing the `// @map` marker.
+    pub fn load(&self) -> Result<(String, String), GlError> {
+        match self {
+            Self::Embedded => Ok((include_str!("main.vert.glsl").to_owned(), include_str!("main.frag.glsl").to_owned())),
+            Self::Dir(dir) => {
+                let read = |name: &str| {
+                    let path = dir.join(name);
+                    std::fs::read_to_string(&path).map_err(|source| GlError::Io { path, source })
+                };
+                Ok((read(VERT_FILE)?, read(FRAG_FILE)?))
+            }
+        }
+    }
+
+    /// Only sources on disk can change under us.
+    pub fn watcher(&self) -> Option<ShaderWatcher> {
+        match self {
+            Self::Embedded => None,
+            Self::Dir(dir) => Some(ShaderWatcher::new(vec![dir.join(VERT_FILE), dir.join(FRAG_FILE)])),
+        }
+    }
+}
+
+
+/// Watches shader files by polling their modification times.
+pub struct ShaderWatcher {
+    paths: Vec<PathBuf>,
+    mtimes: Vec<Option<SystemTime>>,
+    last_poll: Instant,
+}
+
+
+impl ShaderWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let mtimes = paths.iter().map(|path| modified(path)).collect();
+        Self {
+            paths,
+            mtimes,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Returns true once for every batch of changes on disk.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (path, mtime) in self.paths.iter().zip(self.mtimes.iter_mut()) {
+            let current = modified(path);
+            if current != *mtime {
+                *mtime = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+
+/// Why the last shader rebuild failed. The source of the offending stage is kept so
+/// the log's line numbers can be shown next to the code they point at.
+pub struct ShaderFailure {
+    pub error: GlError,
+    /// The stage's source as it is on disk, before the graph's `map()` replaced the marker
+    pub source: Option<String>,
+    injection: Option<Injection>,
+}
+
+
+/// Where the generated `map()` landed in the compiled fragment shader: the 1-based line
+/// of the `// @map` marker on disk, and how many compiled lines took its place.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Injection {
+    line: usize,
+    len: usize,
+}
+
+impl Injection {
+    fn find(on_disk: &str, compiled: &str) -> Option<Self> {
+        let line = on_disk.lines().position(|line| line.contains(MAP_MARKER))? + 1;
+        let len = (compiled.lines().count() + 1).checked_sub(on_disk.lines().count())?;
+        Some(Self { line, len })
+    }
+}
+
+
+impl ShaderFailure {
+    /// `fragment_source` is the file as loaded and `compiled_fragment` what was handed to
+    /// the driver after `inject_map`.
+    pub fn new(error: GlError, vertex_source: &str, fragment_source: &str, compiled_fragment: &str) -> Self {
+        let (source, injection) = match error {
+            GlError::Compile { stage: "vertex", .. } => (Some(vertex_source.to_owned()), None),
+            GlError::Compile { stage: "fragment", .. } => (Some(fragment_source.to_owned()), Injection::find(fragment_source, compiled_fragment)),
+            _ => (None, None),
+        };
+        Self { error, source, injection }
+    }
+
+    /// Line of `source` a compiled line number comes from, and for lines inside the
+    /// generated `map()` which line of it they are. Lines of `map()` point at the marker.
+    fn source_line(&self, compiled: usize) -> (usize, Option<usize>) {
+        match self.injection {
+            Some(Injection { line, len }) if compiled >= line + len => (compiled + 1 - len, None),
+            Some(Injection { line, .. }) if compiled >= line => (line, Some(compiled + 1 - line)),
+            _ => (compiled, None),
+        }
+    }
+}
+
+
+impl From<GlError> for ShaderFailure {
+    fn from(error: GlError) -> Self {
+        Self { error, source: None, injection: None }
+    }
+}
+
+
+/// Pulls the source line out of an info log line. Handles the Mesa/Intel/AMD style
+/// `0:12(5): error` and `ERROR: 0:12:` as well as the NVIDIA style `0(12) : error`.
+fn log_line_number(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    for i in 0..bytes.len().saturating_sub(2) {
+        let after_digit = i > 0 && bytes[i - 1].is_ascii_digit();
+        if bytes[i] != b'0' || after_digit || !matches!(bytes[i + 1], b':' | b'(') {
+            continue;
+        }
+        let digits: String = line[i + 2..].chars().take_while(|c| c.is_ascii_digit()).collect();
+        if let Ok(number) = digits.parse() {
+            return Some(number);
+        }
+    }
+    None
+}
+
+
+/// Shows the failure in a window until it is dismissed. Returns false once the user closes it.
+pub fn error_window(ctx: &egui::Context, failure: &ShaderFailure) -> bool {
+    let mut open = true;
+    egui::Window::new("Shader error")
+        .open(&mut open)
+        .default_width(520.0)
+        .show(ctx, |ui| {
+            let (summary, log) = match &failure.error {
+                GlError::Compile { stage, log } => (format!("The {stage} shader failed to compile"), log.as_str()),
+                GlError::Link { log } => ("The shader program failed to link".to_owned(), log.as_str()),
+                other => (other.to_string(), ""),
+            };
+            ui.label(format!("{summary}. The last working shader is still in use."));
+            ui.separator();
+
+            let source_lines: Vec<&str> = failure.source.as_deref().map(|s| s.lines().collect()).unwrap_or_default();
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for line in log.lines().filter(|line| !line.trim().is_empty()) {
+                    ui.label(RichText::new(line).monospace().color(Color32::from_rgb(240, 110, 100)));
+
+                    let Some((number, generated)) = log_line_number(line).map(|number| failure.source_line(number)) else { continue };
+                    let Some(code) = number.checked_sub(1).and_then(|i| source_lines.get(i)) else { continue };
+                    let code = match generated {
+                        Some(offset) => format!("{}  (line {offset} of the generated map())", code.trim()),
+                        None => code.trim_end().to_owned(),
+                    };
+                    ui.label(RichText::new(format!("{number:>5} | {code}")).monospace());
+                }
+            });
+        });
+    open
+}
+
+
+#[cfg(test)]
+mod tests {
+    use meshview::sdf_graph::{self, SdfNode};
+
+    use super::*;
+
+    #[test]
+    fn line_numbers_come_out_of_every_log_style() {
+        assert_eq!(log_line_number("0:12(5): error: `foo' undeclared"), Some(12));
+        assert_eq!(log_line_number("ERROR: 0:7: 'bar' : syntax error"), Some(7));
+        assert_eq!(log_line_number("0(134) : error C1008: undefined variable"), Some(134));
+        assert_eq!(log_line_number("error: linking failed"), None);
+        // the 0 of 10 or 0.5 is not a source-string index
+        assert_eq!(log_line_number("at 10:3 and 0.5"), None);
+    }
+
+    #[test]
+    fn fragment_lines_map_back_to_the_file_on_disk() {
+        let on_disk = "#version 330\nfloat before;\n// @map\nvoid main() {\n    oops;\n}\n";
+        let compiled = sdf_graph::inject_map(on_disk, &SdfNode::Fractal).unwrap();
+        let error = GlError::Compile { stage: "fragment", log: String::new() };
+        let failure = ShaderFailure::new(error, "", on_disk, &compiled);
+        assert_eq!(failure.source.as_deref(), Some(on_disk));
+
+        let oops = compiled.lines().position(|line| line.contains("oops")).unwrap() + 1;
+        assert!(oops > 5);
+        assert_eq!(failure.source_line(oops), (5, None));
+        assert_eq!(failure.source_line(2), (2, None));
+        assert_eq!(failure.source_line(3), (3, Some(1)));
+        assert_eq!(failure.source_line(oops - 2), (3, Some(oops - 4)));
+    }
+
+    #[test]
+    fn vertex_lines_are_left_alone() {
+        let error = GlError::Compile { stage: "vertex", log: String::new() };
+        let failure = ShaderFailure::new(error, "void main() {}\n", "// @map\n", "float map() {}\n\n");
+        assert_eq!(failure.source.as_deref(), Some("void main() {}\n"));
+        assert_eq!(failure.source_line(9), (9, None));
+    }
+}
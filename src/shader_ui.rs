@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+
+use eframe::{egui::{self, Color32, DragValue}, glow};
+use meshview::shader_params::{ParamValue, ShaderParams};
+use nalgebra::Vector3;
+
+use crate::uniforms::UniformRegistry;
+
+
+/// The widget requested by a `// @ui` annotation. Ranges are inclusive.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Widget {
+    Slider(f64, f64),
+    /// A drag value with `-` and `+` buttons
+    Stepper(f64, f64),
+    Drag(f64, f64),
+    Checkbox,
+    Color,
+}
+
+
+/// A control generated from a line like
+///
+/// ```glsl
+/// uniform float u_Exp; // @ui slider 0 30 label="Power" default=8 when=u_Fractal==FRACTAL_MANDELBULB
+/// ```
+///
+/// `when` hides the control unless an int uniform has the given value, which may be
+/// a number or a `#define` from the same source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UiControl {
+    pub name: String,
+    pub label: String,
+    pub glsl_type: String,
+    pub widget: Widget,
+    pub default: Option<f64>,
+    pub when: Option<(String, i32)>,
+}
+
+
+impl UiControl {
+    /// The value used when nothing is stored for this uniform yet.
+    fn initial_value(&self) -> ParamValue {
+        let number = self.default.unwrap_or(match self.widget {
+            Widget::Slider(min, _) | Widget::Stepper(min, _) | Widget::Drag(min, _) => min.max(0.0),
+            _ => 0.0,
+        });
+        match self.glsl_type.as_str() {
+            "int" => ParamValue::Int(number as i32),
+            "uint" => ParamValue::UInt(number as u32),
+            "bool" => ParamValue::Bool(number != 0.0),
+            "vec3" if self.widget == Widget::Color && self.default.is_none() => ParamValue::Vec3(Vector3::repeat(1.0)),
+            "vec3" => ParamValue::Vec3(Vector3::repeat(number as f32)),
+            _ => ParamValue::Float(number as f32),
+        }
+    }
+
+    fn accepts(&self, value: &ParamValue) -> bool {
+        matches!(
+            (self.glsl_type.as_str(), value),
+            ("float", ParamValue::Float(_))
+                | ("int", ParamValue::Int(_))
+                | ("uint", ParamValue::UInt(_))
+                | ("bool", ParamValue::Bool(_))
+                | ("vec3", ParamValue::Vec3(_))
+        )
+    }
+}
+
+
+/// Splits on whitespace, keeping double-quoted runs together without their quotes.
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+
+fn parse_control(decl: &str, annotation: &str, defines: &HashMap<&str, i32>) -> Result<UiControl, String> {
+    let mut words = decl.split_whitespace();
+    let glsl_type = words.next().ok_or("missing uniform type")?.to_owned();
+    let name = words.next().ok_or("missing uniform name")?.to_owned();
+    // one widget can't set a whole array
+    if name.contains('[') || words.next().is_some_and(|word| word.starts_with('[')) {
+        let name = name.split('[').next().unwrap_or_default();
+        return Err(format!("{name}: arrays can't have @ui controls"));
+    }
+
+    let tokens = tokenize(annotation);
+    let (kind, rest) = tokens.split_first().ok_or_else(|| format!("{name}: @ui needs a widget"))?;
+
+    let numbers: Vec<f64> = rest.iter().map_while(|token| token.parse().ok()).collect();
+    let range = || match numbers[..] {
+        [min, max, ..] => Ok((min, max)),
+        _ => Err(format!("{name}: `{kind}` needs a min and max")),
+    };
+    let widget = match kind.as_str() {
+        "slider" => range().map(|(min, max)| Widget::Slider(min, max))?,
+        "stepper" => range().map(|(min, max)| Widget::Stepper(min, max))?,
+        "drag" => range().map(|(min, max)| Widget::Drag(min, max))?,
+        "checkbox" => Widget::Checkbox,
+        "color" => Widget::Color,
+        other => return Err(format!("{name}: unknown widget `{other}`")),
+    };
+
+    let supported = match widget {
+        Widget::Slider(..) | Widget::Stepper(..) => matches!(glsl_type.as_str(), "float" | "int" | "uint"),
+        Widget::Drag(..) => matches!(glsl_type.as_str(), "float" | "int" | "uint" | "vec3"),
+        Widget::Checkbox => glsl_type == "bool",
+        Widget::Color => glsl_type == "vec3",
+    };
+    if !supported {
+        return Err(format!("{name}: `{kind}` does not work on a {glsl_type}"));
+    }
+
+    let mut control = UiControl {
+        label: name.strip_prefix("u_").unwrap_or(&name).to_owned(),
+        name: name.clone(),
+        glsl_type,
+        widget,
+        default: None,
+        when: None,
+    };
+
+    for option in &rest[numbers.len()..] {
+        let (key, value) = option.split_once('=').ok_or_else(|| format!("{name}: expected key=value, got `{option}`"))?;
+        match key {
+            "label" => control.label = value.to_owned(),
+            "default" => control.default = Some(value.parse().map_err(|_| format!("{name}: bad default `{value}`"))?),
+            "when" => {
+                let (uniform, expected) = value.split_once("==").ok_or_else(|| format!("{name}: `when` needs uniform==value"))?;
+                let expected = expected
+                    .parse()
+                    .ok()
+                    .or_else(|| defines.get(expected).copied())
+                    .ok_or_else(|| format!("{name}: unknown value `{expected}`"))?;
+                control.when = Some((uniform.to_owned(), expected));
+            }
+            other => return Err(format!("{name}: unknown option `{other}`")),
+        }
+    }
+
+    Ok(control)
+}
+
+
+/// Collects every `// @ui` annotated uniform in `source`. Malformed annotations come back
+/// as errors so they can be shown where the control would have been.
+pub fn parse_controls(source: &str) -> Vec<Result<UiControl, String>> {
+    let defines: HashMap<&str, i32> = source
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("#define "))
+        .filter_map(|define| {
+            let mut words = define.split_whitespace();
+            Some((words.next()?, words.next()?.parse().ok()?))
+        })
+        .collect();
+
+    source
+        .lines()
+        .filter_map(|line| {
+            let decl = line.trim_start().strip_prefix("uniform ")?;
+            let (decl, comment) = decl.split_once(';')?;
+            let annotation = comment.trim_start().strip_prefix("//")?.trim_start().strip_prefix("@ui")?;
+            Some(parse_control(decl, annotation, &defines))
+        })
+        .collect()
+}
+
+
+fn number_widget<T: egui::emath::Numeric>(ui: &mut egui::Ui, widget: &Widget, value: &mut T) {
+    match *widget {
+        Widget::Slider(min, max) => {
+            ui.add(egui::Slider::new(value, T::from_f64(min)..=T::from_f64(max)));
+        }
+        Widget::Drag(min, max) => {
+            ui.add(DragValue::new(value).range(T::from_f64(min)..=T::from_f64(max)));
+        }
+        Widget::Stepper(min, max) => {
+            let step = |value: &mut T, delta: f64| *value = T::from_f64((value.to_f64() + delta).clamp(min, max));
+            if ui.button("-").clicked() {
+                step(value, -1.0);
+            }
+            ui.add(DragValue::new(value).range(T::from_f64(min)..=T::from_f64(max)));
+            if ui.button("+").clicked() {
+                step(value, 1.0);
+            }
+        }
+        Widget::Checkbox | Widget::Color => {}
+    }
+}
+
+
+/// Draws one row per control, editing the matching entry in `params`. Controls for which
+/// `locked` returns true are shown disabled, e.g. while the timeline drives them.
+pub fn controls_ui(ui: &mut egui::Ui, controls: &[Result<UiControl, String>], params: &mut ShaderParams, locked: impl Fn(&str) -> bool) {
+    for control in controls {
+        let control = match control {
+            Ok(control) => control,
+            Err(err) => {
+                ui.colored_label(Color32::from_rgb(240, 110, 100), err);
+                continue;
+            }
+        };
+
+        if let Some((uniform, expected)) = &control.when {
+            let current = match params.get(uniform) {
+                Some(ParamValue::Int(v)) => Some(*v),
+                Some(ParamValue::UInt(v)) => Some(*v as i32),
+                _ => None,
+            };
+            if current != Some(*expected) {
+                continue;
+            }
+        }
+
+        let value = params.entry(control.name.clone()).or_insert_with(|| control.initial_value());
+        if !control.accepts(value) {
+            *value = control.initial_value();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(&control.label);
+            ui.add_enabled_ui(!locked(&control.name), |ui| match value {
+                ParamValue::Float(v) => number_widget(ui, &control.widget, v),
+                ParamValue::Int(v) => number_widget(ui, &control.widget, v),
+                ParamValue::UInt(v) => number_widget(ui, &control.widget, v),
+                ParamValue::Bool(v) => {
+                    ui.checkbox(v, "");
+                }
+                ParamValue::Vec3(v) if control.widget == Widget::Color => {
+                    let mut rgb = [v.x, v.y, v.z];
+                    ui.color_edit_button_rgb(&mut rgb);
+                    *v = Vector3::from(rgb);
+                }
+                ParamValue::Vec3(v) => {
+                    for c in v.iter_mut() {
+                        number_widget(ui, &control.widget, c);
+                    }
+                }
+            });
+        });
+    }
+}
+
+
+/// Uploads every stored value to the program in use.
+pub fn upload(gl: &glow::Context, uniforms: &UniformRegistry, params: &ShaderParams) {
+    for (name, value) in params {
+        match *value {
+            ParamValue::Float(v) => uniforms.set(gl, name, v),
+            ParamValue::Int(v) => uniforms.set(gl, name, v),
+            ParamValue::UInt(v) => uniforms.set(gl, name, v),
+            ParamValue::Bool(v) => uniforms.set(gl, name, v),
+            ParamValue::Vec3(v) => uniforms.set(gl, name, v),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_keeps_quoted_runs_together() {
+        assert_eq!(tokenize(r#" slider 0  30 label="Bulb power" "#), ["slider", "0", "30", "label=Bulb power"]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn parses_well_formed_annotations() {
+        let source = "
+            #define FRACTAL_MANDELBOX 1
+            uniform float u_Exp; // @ui slider 0 30 label=\"Power\" default=8 when=u_Fractal==FRACTAL_MANDELBOX
+            uniform uint u_Iters;    // @ui stepper 1 40
+            uniform bool u_Shadows; // @ui checkbox
+            uniform vec3 u_Tint; // @ui color
+            uniform int u_Plain;
+            uniform float u_Other; // a comment
+        ";
+        let controls: Vec<_> = parse_controls(source).into_iter().map(Result::unwrap).collect();
+        assert_eq!(controls.len(), 4);
+
+        assert_eq!(controls[0], UiControl {
+            name: "u_Exp".into(),
+            label: "Power".into(),
+            glsl_type: "float".into(),
+            widget: Widget::Slider(0.0, 30.0),
+            default: Some(8.0),
+            when: Some(("u_Fractal".into(), 1)),
+        });
+        assert_eq!(controls[1].label, "Iters");
+        assert_eq!(controls[1].widget, Widget::Stepper(1.0, 40.0));
+        assert_eq!(controls[1].initial_value(), ParamValue::UInt(1));
+        assert_eq!(controls[2].widget, Widget::Checkbox);
+        assert_eq!(controls[3].initial_value(), ParamValue::Vec3(Vector3::repeat(1.0)));
+    }
+
+    #[test]
+    fn malformed_annotations_are_errors() {
+        let defines = HashMap::new();
+        for (decl, annotation, message) in [
+            ("float u_A", "", "u_A: @ui needs a widget"),
+            ("float u_A", "knob 0 1", "u_A: unknown widget `knob`"),
+            ("float u_A", "slider 0", "u_A: `slider` needs a min and max"),
+            ("bool u_A", "slider 0 1", "u_A: `slider` does not work on a bool"),
+            ("float u_A", "checkbox", "u_A: `checkbox` does not work on a float"),
+            ("float u_A", "slider 0 1 big", "u_A: expected key=value, got `big`"),
+            ("float u_A", "slider 0 1 colour=red", "u_A: unknown option `colour`"),
+            ("float u_A", "slider 0 1 default=high", "u_A: bad default `high`"),
+            ("float u_A", "slider 0 1 when=u_B", "u_A: `when` needs uniform==value"),
+            ("float u_A", "slider 0 1 when=u_B==MISSING", "u_A: unknown value `MISSING`"),
+            ("float", "slider 0 1", "missing uniform name"),
+        ] {
+            assert_eq!(parse_control(decl, annotation, &defines), Err(message.to_owned()), "{decl} // @ui {annotation}");
+        }
+    }
+
+    #[test]
+    fn array_uniforms_are_rejected() {
+        let source = "
+            uniform float u_Weights[3]; // @ui slider 0 1
+            uniform vec3 u_Colors [2]; // @ui color
+        ";
+        assert_eq!(parse_controls(source), [
+            Err("u_Weights: arrays can't have @ui controls".to_owned()),
+            Err("u_Colors: arrays can't have @ui controls".to_owned()),
+        ]);
+    }
+}
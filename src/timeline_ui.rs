@@ -0,0 +1,33 @@
+use eframe::egui::{self, Color32, Stroke};
+use meshview::animation::Timeline;
+
+
+/// Draws the timeline with a marker for every key and lets the user click or drag to scrub.
+/// Returns true when `time` was changed.
+pub fn scrub_bar(ui: &mut egui::Ui, timeline: &Timeline, time: &mut f32) -> bool {
+    let (rect, response) =
+        ui.allocate_exact_size(egui::vec2(ui.available_width(), 22.0), egui::Sense::click_and_drag());
+
+    let duration = timeline.duration.max(1.0e-3);
+    let x_at = |t: f32| rect.left() + rect.width() * (t / duration).clamp(0.0, 1.0);
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    for t in timeline.key_times() {
+        let x = x_at(t);
+        painter.line_segment(
+            [egui::pos2(x, rect.top() + 5.0), egui::pos2(x, rect.bottom() - 5.0)],
+            Stroke::new(2.0, Color32::from_rgb(230, 180, 60))
+        );
+    }
+
+    let x = x_at(*time);
+    painter.line_segment([egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())], Stroke::new(2.0, Color32::WHITE));
+
+    if let Some(pos) = response.interact_pointer_pos() {
+        *time = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0) * duration;
+        return true;
+    }
+    false
+}
@@ -0,0 +1,94 @@
+use eframe::egui::{self, DragValue};
+use meshview::orbit_trap::{OrbitTraps, Trap, TrapChannel, TrapShape, MAX_TRAPS};
+
+use crate::widgets::vec3;
+
+
+fn scalar(ui: &mut egui::Ui, label: &str, value: &mut f32) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(DragValue::new(value).speed(0.01));
+    });
+}
+
+fn trap_ui(ui: &mut egui::Ui, trap: &mut Trap) -> bool {
+    let mut remove = false;
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut trap.enabled, "");
+
+        let mut shape = trap.shape.index();
+        egui::ComboBox::from_id_salt("shape")
+            .width(72.0)
+            .show_index(ui, &mut shape, TrapShape::NAMES.len(), |i| TrapShape::NAMES[i]);
+        if shape != trap.shape.index() {
+            trap.shape = TrapShape::from_index(shape);
+        }
+
+        egui::ComboBox::from_id_salt("channel")
+            .width(88.0)
+            .selected_text(trap.channel.name())
+            .show_ui(ui, |ui| {
+                for channel in TrapChannel::ALL {
+                    ui.selectable_value(&mut trap.channel, channel, channel.name());
+                }
+            });
+
+        remove = ui.small_button("x").clicked();
+    });
+
+    match &mut trap.shape {
+        TrapShape::Sphere { center, radius } => {
+            vec3(ui, "Center", center);
+            scalar(ui, "Radius", radius);
+        }
+        TrapShape::Point { center } | TrapShape::Cross { center } => vec3(ui, "Center", center),
+        TrapShape::Plane { normal, offset } => {
+            vec3(ui, "Normal", normal);
+            scalar(ui, "Offset", offset);
+        }
+        TrapShape::Axis { point, direction } => {
+            vec3(ui, "Point", point);
+            vec3(ui, "Direction", direction);
+        }
+        TrapShape::Box { center, half_extents } => {
+            vec3(ui, "Center", center);
+            vec3(ui, "Half extents", half_extents);
+        }
+    }
+    remove
+}
+
+
+/// Trap list. Each trap feeds one channel; the "Trap channels" coloring mode shows the
+/// red, green and blue ones, the other modes read brightness.
+pub fn orbit_traps_ui(ui: &mut egui::Ui, orbit_traps: &mut OrbitTraps) {
+    let mut removed = None;
+    for (i, trap) in orbit_traps.traps.iter_mut().enumerate() {
+        ui.push_id(i, |ui| {
+            if trap_ui(ui, trap) {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed {
+        orbit_traps.traps.remove(i);
+    }
+
+    let active = orbit_traps.traps.iter().filter(|trap| trap.enabled).count();
+    ui.horizontal(|ui| {
+        if ui.button("Add trap").clicked() {
+            // fill the first color channel no trap feeds yet
+            let channel = TrapChannel::ALL
+                .into_iter()
+                .find(|channel| !orbit_traps.traps.iter().any(|trap| trap.channel == *channel))
+                .unwrap_or(TrapChannel::Brightness);
+            orbit_traps.traps.push(Trap { shape: TrapShape::from_index(1), channel, enabled: true });
+        }
+        if ui.button("Reset").clicked() {
+            *orbit_traps = OrbitTraps::default();
+        }
+    });
+    if active > MAX_TRAPS {
+        ui.label(format!("Only the first {MAX_TRAPS} enabled traps are used"));
+    }
+}
@@ -0,0 +1,251 @@
+use std::{collections::{HashMap, HashSet}, sync::Mutex};
+
+use eframe::glow::{self, HasContext as _};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+
+
+/// A uniform the linker kept, as reported by `glGetActiveUniform`.
+struct ActiveUniform {
+    location: glow::UniformLocation,
+    gl_type: u32,
+    size: i32,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniformError {
+    Unknown(String),
+    TypeMismatch { name: String, expected: String, found: String },
+}
+
+
+impl std::fmt::Display for UniformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "no uniform named {name} in the shader"),
+            Self::TypeMismatch { name, expected, found } => write!(f, "{name} is a {expected} but was set with a {found}"),
+        }
+    }
+}
+
+
+impl std::error::Error for UniformError {}
+
+
+/// Rust types that map onto a GLSL uniform type.
+pub trait UniformValue {
+    const GL_TYPE: u32;
+
+    /// Number of array elements written.
+    fn count(&self) -> i32 {
+        1
+    }
+
+    /// # Safety
+    /// The program owning `location` must be in use.
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation);
+}
+
+
+impl UniformValue for f32 {
+    const GL_TYPE: u32 = glow::FLOAT;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_1_f32(Some(location), *self);
+    }
+}
+
+impl UniformValue for i32 {
+    const GL_TYPE: u32 = glow::INT;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_1_i32(Some(location), *self);
+    }
+}
+
+/// Texture unit a `sampler2D` reads from.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureUnit(pub i32);
+
+impl UniformValue for TextureUnit {
+    const GL_TYPE: u32 = glow::SAMPLER_2D;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_1_i32(Some(location), self.0);
+    }
+}
+
+impl UniformValue for u32 {
+    const GL_TYPE: u32 = glow::UNSIGNED_INT;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_1_u32(Some(location), *self);
+    }
+}
+
+impl UniformValue for bool {
+    const GL_TYPE: u32 = glow::BOOL;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_1_i32(Some(location), *self as i32);
+    }
+}
+
+impl UniformValue for Vector2<f32> {
+    const GL_TYPE: u32 = glow::FLOAT_VEC2;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_2_f32(Some(location), self.x, self.y);
+    }
+}
+
+impl UniformValue for Vector3<f32> {
+    const GL_TYPE: u32 = glow::FLOAT_VEC3;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_3_f32(Some(location), self.x, self.y, self.z);
+    }
+}
+
+impl UniformValue for Vector4<f32> {
+    const GL_TYPE: u32 = glow::FLOAT_VEC4;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_4_f32(Some(location), self.x, self.y, self.z, self.w);
+    }
+}
+
+impl UniformValue for Matrix4<f32> {
+    const GL_TYPE: u32 = glow::FLOAT_MAT4;
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_matrix_4_f32_slice(Some(location), false, self.as_slice());
+    }
+}
+
+impl<const N: usize> UniformValue for [i32; N] {
+    const GL_TYPE: u32 = glow::INT;
+    fn count(&self) -> i32 {
+        N as i32
+    }
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        gl.uniform_1_i32_slice(Some(location), self);
+    }
+}
+
+impl<const N: usize> UniformValue for [Vector3<f32>; N] {
+    const GL_TYPE: u32 = glow::FLOAT_VEC3;
+    fn count(&self) -> i32 {
+        N as i32
+    }
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        let flat: Vec<f32> = self.iter().flat_map(|v| [v.x, v.y, v.z]).collect();
+        gl.uniform_3_f32_slice(Some(location), &flat);
+    }
+}
+
+impl<const N: usize> UniformValue for [Vector4<f32>; N] {
+    const GL_TYPE: u32 = glow::FLOAT_VEC4;
+    fn count(&self) -> i32 {
+        N as i32
+    }
+    unsafe fn upload(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+        let flat: Vec<f32> = self.iter().flat_map(|v| [v.x, v.y, v.z, v.w]).collect();
+        gl.uniform_4_f32_slice(Some(location), &flat);
+    }
+}
+
+
+fn type_name(gl_type: u32, size: i32) -> String {
+    let base = match gl_type {
+        glow::FLOAT => "float",
+        glow::FLOAT_VEC2 => "vec2",
+        glow::FLOAT_VEC3 => "vec3",
+        glow::FLOAT_VEC4 => "vec4",
+        glow::INT => "int",
+        glow::UNSIGNED_INT => "uint",
+        glow::BOOL => "bool",
+        glow::FLOAT_MAT4 => "mat4",
+        glow::SAMPLER_2D => "sampler2D",
+        other => return format!("GL type 0x{other:x}"),
+    };
+    if size > 1 {
+        format!("{base}[{size}]")
+    } else {
+        base.to_owned()
+    }
+}
+
+
+/// Names declared with `uniform` in the sources, whether or not the linker kept them.
+fn declared_uniforms(sources: &[&str]) -> HashSet<String> {
+    sources
+        .iter()
+        .flat_map(|source| source.lines())
+        .filter_map(|line| line.trim_start().strip_prefix("uniform "))
+        .filter_map(|decl| decl.split(';').next()?.split_whitespace().last())
+        .map(|name| name.split('[').next().unwrap_or(name).to_owned())
+        .collect()
+}
+
+
+/// Uniform locations looked up once after linking, with typed setters. Mistakes are
+/// collected and reported once per uniform instead of failing silently every frame.
+pub struct UniformRegistry {
+    uniforms: HashMap<String, ActiveUniform>,
+    declared: HashSet<String>,
+    reported: Mutex<HashSet<String>>,
+    problems: Mutex<Vec<UniformError>>,
+}
+
+
+impl UniformRegistry {
+    pub fn new(gl: &glow::Context, program: glow::Program, sources: &[&str]) -> Self {
+        let mut uniforms = HashMap::new();
+        unsafe {
+            for index in 0..gl.get_active_uniforms(program) {
+                let Some(active) = gl.get_active_uniform(program, index) else { continue };
+                let Some(location) = gl.get_uniform_location(program, &active.name) else { continue };
+                // arrays are reported as `u_Palette[0]`
+                let name = active.name.strip_suffix("[0]").unwrap_or(&active.name).to_owned();
+                uniforms.insert(name, ActiveUniform { location, gl_type: active.utype, size: active.size });
+            }
+        }
+
+        Self {
+            uniforms,
+            declared: declared_uniforms(sources),
+            reported: Mutex::new(HashSet::new()),
+            problems: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Uploads `value` to the program currently in use. Uniforms the compiler optimized
+    /// away are skipped quietly; unknown names and type mismatches are reported.
+    pub fn set<T: UniformValue>(&self, gl: &glow::Context, name: &str, value: T) {
+        if let Err(err) = self.try_set(gl, name, &value) {
+            if self.reported.lock().unwrap().insert(name.to_owned()) {
+                self.problems.lock().unwrap().push(err);
+            }
+        }
+    }
+
+    pub fn try_set<T: UniformValue>(&self, gl: &glow::Context, name: &str, value: &T) -> Result<(), UniformError> {
+        let Some(uniform) = self.uniforms.get(name) else {
+            return if self.declared.contains(name) {
+                Ok(())
+            } else {
+                Err(UniformError::Unknown(name.to_owned()))
+            };
+        };
+
+        if uniform.gl_type != T::GL_TYPE || value.count() > uniform.size {
+            return Err(UniformError::TypeMismatch {
+                name: name.to_owned(),
+                expected: type_name(uniform.gl_type, uniform.size),
+                found: type_name(T::GL_TYPE, value.count()),
+            });
+        }
+
+        unsafe {
+            value.upload(gl, &uniform.location);
+        }
+        Ok(())
+    }
+
+    /// Problems found since the last call, each uniform at most once per program.
+    pub fn take_problems(&self) -> Vec<UniformError> {
+        std::mem::take(&mut *self.problems.lock().unwrap())
+    }
+}
@@ -0,0 +1,23 @@
+use std::ops::RangeInclusive;
+
+use eframe::egui::{self, DragValue};
+use nalgebra::Vector3;
+
+
+/// A label followed by a drag value for each component.
+pub fn vec3(ui: &mut egui::Ui, label: &str, value: &mut Vector3<f32>) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(DragValue::new(&mut value.x).speed(0.01));
+        ui.add(DragValue::new(&mut value.y).speed(0.01));
+        ui.add(DragValue::new(&mut value.z).speed(0.01));
+    });
+}
+
+/// A label followed by a slider.
+pub fn slider(ui: &mut egui::Ui, label: &str, value: &mut f32, range: RangeInclusive<f32>) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::Slider::new(value, range));
+    });
+}